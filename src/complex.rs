@@ -0,0 +1,478 @@
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+use crate::poly::Polynomial;
+
+/// Iterations after which Durand–Kerner settles for the roots it has.
+const ITERATION_LIMIT:usize = 200;
+
+/// `re + im i` over a real number type, so `sqrt(-4)` is `2i` rather than undefined.
+/// Results with a real value, such as `sqrt(4)`, stay exactly what the real type gives.
+#[derive(Clone,Default,PartialEq,PartialOrd)]
+pub struct Complex<N> {
+    pub re:N,
+    pub im:N,
+}
+
+impl<N:Numeric> Complex<N> {
+    pub fn new(re:N, im:N) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re:N) -> Self {
+        Self::new(re, N::from(0))
+    }
+
+    fn i() -> Self {
+        Self::new(N::from(0), N::from(1))
+    }
+
+    /// Modulus `|z|`, a real number, `None` when out of range.
+    /// Taken as `m sqrt(1 + (n/m)^2)` with `m` the larger part, so no part is squared past the range of the number type.
+    fn modulus(&self) -> Option<N> {
+        if self.im.is_zero() {
+            return Some(self.re.abs());
+        }
+        if self.re.is_zero() {
+            return Some(self.im.abs());
+        }
+        let (large,small) = self.parts_by_size();
+        let ratio = small.checked_div(&large)?;
+        large.checked_mul(&(N::from(1) + ratio.clone() * &ratio).sqrt()?)
+    }
+
+    /// `|re|` and `|im|`, the larger first.
+    fn parts_by_size(&self) -> (N,N) {
+        let (re,im) = (self.re.abs(),self.im.abs());
+        if re >= im { (re,im) } else { (im,re) }
+    }
+
+    /// Angle to the positive real axis in `(-π, π]`, `None` at zero.
+    /// `arg(z) = 2 atan(im / (|z| + re))` off the negative real axis.
+    fn arg(&self) -> Option<N> {
+        if self.im.is_zero() {
+            return match () {
+                _ if self.re.is_positive() => Some(N::from(0)),
+                _ if self.re.is_negative() => Some(N::pi()),
+                _ => None,
+            };
+        }
+        let half = self.im.checked_div(&self.modulus()?.checked_add(&self.re)?)?.atan()?;
+        Some(N::from(2) * half)
+    }
+
+    /// `self^n` by repeated squaring, exact for exact parts, `None` when out of range.
+    fn powi(&self, mut n:u32) -> Option<Self> {
+        let mut result = Self::real(N::from(1));
+        let mut base = self.clone();
+        while n > 0 {
+            if n % 2 == 1 {
+                result = result.checked_mul(&base)?;
+            }
+            n /= 2;
+            if n > 0 {
+                base = base.checked_mul(&base)?;
+            }
+        }
+        Some(result)
+    }
+
+    /// `cosh(x)` and `sinh(x)` of a real `x`.
+    fn hyperbolic(x:&N) -> Option<(N,N)> {
+        let (up,down) = (x.exp()?,(-x.clone()).exp()?);
+        Some((up.checked_add(&down)? / N::from(2),up.checked_sub(&down)? / N::from(2)))
+    }
+
+    /// Magnitude used to compare roots, the larger part being cheaper than the modulus and never out of range.
+    fn size(&self) -> N {
+        self.parts_by_size().0
+    }
+
+    /// Drops a part lost in the rounding noise of the other, so a real root is not printed with `1e-40i`.
+    fn clean(self) -> Self {
+        let noise = self.size() / N::from(1_000_000) / N::from(1_000_000);
+        let re = if self.re.abs() <= noise { N::from(0) } else { self.re };
+        let im = if self.im.abs() <= noise { N::from(0) } else { self.im };
+        Self::new(re, im)
+    }
+}
+
+/// Roots of a polynomial with real coefficients: the real ones exactly as the real type finds them,
+/// then the conjugate pairs of what is left after dividing those out.
+fn real_polynomial_roots<N:Numeric>(coefs:&[N]) -> Result<Vec<Complex<N>>,EvalError> {
+    let poly = Polynomial::new(coefs.to_vec());
+    let real = poly.roots()?;
+    let mut rest = poly;
+    for root in &real {
+        rest = rest.deflate(root)?;
+    }
+    let mut roots = real.into_iter().map(Complex::real).collect::<Vec<Complex<N>>>();
+    match rest.coefs() {
+        [c,b,a] => {
+            let (a,b,c) = (Complex::real(a.clone()),Complex::real(b.clone()),Complex::real(c.clone()));
+            let disc = b.clone() * &b - Complex::real(N::from(4)) * &a * &c;
+            let disc = disc.sqrt().ok_or_else(|| EvalError::Undefined(format!("sqrt({})",disc)))?;
+            let twice = Complex::real(N::from(2)) * a;
+            roots.push((-b.clone() - &disc) / &twice);
+            roots.push((-b + disc) / twice);
+        }
+        coefs if coefs.len() > 3 => roots.extend(durand_kerner(&coefs.iter().cloned().map(Complex::real).collect::<Vec<Complex<N>>>())?),
+        _ => {}
+    }
+    Ok(roots)
+}
+
+/// Durand–Kerner iteration, refining a guess for every root of the polynomial at once.
+fn durand_kerner<N:Numeric>(coefs:&[Complex<N>]) -> Result<Vec<Complex<N>>,EvalError> {
+    let poly = Polynomial::new(coefs.to_vec());
+    let lead = poly.coefs()[poly.degree()].clone();
+    let seed = Complex::new(N::from(4) / N::from(10), N::from(9) / N::from(10));
+    let mut roots = (0..poly.degree() as u32).map(|k| seed.powi(k).expect("powers of the seed stay below 1 in size")).collect::<Vec<Complex<N>>>();
+    for _ in 0..ITERATION_LIMIT {
+        let mut settled = true;
+        for k in 0..roots.len() {
+            // A guess whose step is out of range is left where it is, the others moving on.
+            let others = roots.iter().enumerate()
+                .filter(|(j,_)| *j != k)
+                .try_fold(lead.clone(), |acc,(_,it)| acc.checked_mul(&roots[k].checked_sub(it)?));
+            let Some(step) = others.and_then(|others| poly.eval(&roots[k]).ok()?.checked_div(&others)) else { continue };
+            let Some(next) = roots[k].checked_sub(&step).map(|it| it.round_working()) else { continue };
+            if next != roots[k] && step.size() > N::resolution() * (N::from(1) + roots[k].size()) {
+                settled = false;
+            }
+            roots[k] = next;
+        }
+        if settled {
+            break;
+        }
+    }
+    Ok(roots.into_iter().map(Complex::clean).collect())
+}
+
+impl<N:Numeric> Display for Complex<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.im.is_zero() {
+            return write!(f,"{}",self.re);
+        }
+        let sign = if self.im.is_negative() { "-" } else if self.re.is_zero() { "" } else { "+" };
+        let im = self.im.abs();
+        let im = if im == N::from(1) { String::new() } else { im.to_string() };
+        if self.re.is_zero() {
+            write!(f,"{}{}i",sign,im)
+        } else {
+            write!(f,"{}{}{}i",self.re,sign,im)
+        }
+    }
+}
+
+impl<N:Numeric> Debug for Complex<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{}",self)
+    }
+}
+
+impl<N:Numeric> From<i32> for Complex<N> {
+    fn from(n:i32) -> Self {
+        Self::real(N::from(n))
+    }
+}
+
+impl<N:Numeric> Numeric for Complex<N> {
+    fn parse(s:&str) -> Option<Self> {
+        N::parse(s).map(Self::real)
+    }
+
+    fn from_i128(n:i128) -> Option<Self> {
+        N::from_i128(n).map(Self::real)
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        if self.im.is_zero() { self.re.to_i128() } else { None }
+    }
+
+    fn to_fraction(&self) -> Option<(i128,i128)> {
+        if self.im.is_zero() { self.re.to_fraction() } else { None }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.re.is_zero() && self.im.is_zero()
+    }
+
+    fn is_integer(&self) -> bool {
+        self.im.is_zero() && self.re.is_integer()
+    }
+
+    fn is_negative(&self) -> bool {
+        self.im.is_zero() && self.re.is_negative()
+    }
+
+    fn is_positive(&self) -> bool {
+        self.im.is_zero() && self.re.is_positive()
+    }
+
+    /// The modulus, or the larger part where the modulus is out of range, near enough to order pivots by;
+    /// `checked_abs` reports that case instead.
+    fn abs(&self) -> Self {
+        Self::real(self.modulus().unwrap_or_else(|| self.size()))
+    }
+
+    fn checked_abs(&self) -> Option<Self> {
+        self.modulus().map(Self::real)
+    }
+
+    fn signum(&self) -> Self {
+        if self.im.is_zero() {
+            return Self::real(self.re.signum());
+        }
+        self.clone() / self.abs()
+    }
+
+    fn total_cmp(&self, other:&Self) -> Ordering {
+        self.re.total_cmp(&other.re).then_with(|| self.im.total_cmp(&other.im))
+    }
+
+    fn round_dp(&self, dp:u32) -> Self {
+        Self::new(self.re.round_dp(dp), self.im.round_dp(dp))
+    }
+
+    fn resolution() -> Self {
+        Self::real(N::resolution())
+    }
+
+    fn round_working(&self) -> Self {
+        Self::new(self.re.round_working(), self.im.round_working())
+    }
+
+    fn checked_add(&self, other:&Self) -> Option<Self> {
+        Some(Self::new(self.re.checked_add(&other.re)?, self.im.checked_add(&other.im)?))
+    }
+
+    fn checked_sub(&self, other:&Self) -> Option<Self> {
+        Some(Self::new(self.re.checked_sub(&other.re)?, self.im.checked_sub(&other.im)?))
+    }
+
+    /// `(a + bi)(c + di) = (ac - bd) + (ad + bc)i`, the real case kept to a single product.
+    fn checked_mul(&self, other:&Self) -> Option<Self> {
+        if self.im.is_zero() && other.im.is_zero() {
+            return self.re.checked_mul(&other.re).map(Self::real);
+        }
+        let re = self.re.checked_mul(&other.re)?.checked_sub(&self.im.checked_mul(&other.im)?)?;
+        let im = self.re.checked_mul(&other.im)?.checked_add(&self.im.checked_mul(&other.re)?)?;
+        Some(Self::new(re, im))
+    }
+
+    /// Smith's division, which never squares the divisor: with `r = d/c` and `|c| >= |d|`,
+    /// `(a + bi)/(c + di) = ((a + br) + (b - ar)i) / (c + dr)`, and the same with the parts of the divisor swapped otherwise.
+    fn checked_div(&self, other:&Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let (a,b,c,d) = (&self.re,&self.im,&other.re,&other.im);
+        if d.is_zero() {
+            return Some(Self::new(a.checked_div(c)?, b.checked_div(c)?));
+        }
+        let (re,im,denom) = if c.abs() >= d.abs() {
+            let r = d.checked_div(c)?;
+            (a.checked_add(&b.checked_mul(&r)?)?, b.checked_sub(&a.checked_mul(&r)?)?, c.checked_add(&d.checked_mul(&r)?)?)
+        } else {
+            let r = c.checked_div(d)?;
+            (a.checked_mul(&r)?.checked_add(b)?, b.checked_mul(&r)?.checked_sub(a)?, c.checked_mul(&r)?.checked_add(d)?)
+        };
+        Some(Self::new(re.checked_div(&denom)?, im.checked_div(&denom)?))
+    }
+
+    /// The principal value `exp(exp * ln(self))` on every number type, so `(-8)^(1/3)` is `1+1.732…i`
+    /// rather than the odd root `-2`, which `--decimal` and `--float` could not find from a rounded `1/3`.
+    /// Real powers of a base that is not negative, and integer powers, stay real; `(-x)^(k/2)` is exact.
+    fn pow(&self, exp:&Self) -> Option<Self> {
+        if self.im.is_zero() && exp.im.is_zero() {
+            if !self.re.is_negative() || exp.re.is_integer() {
+                return self.re.pow(&exp.re).map(Self::real);
+            }
+            // (-x)^e = x^e (cos eπ + i sin eπ)
+            let magnitude = Self::real(self.re.abs().pow(&exp.re)?);
+            let unit = match (exp.re.clone() * N::from(2)).to_i128() {
+                Some(half_turns) => Self::i().powi(half_turns.rem_euclid(4) as u32)?,
+                None => {
+                    let angle = exp.re.clone() * N::pi();
+                    Self::new(angle.cos()?, angle.sin()?)
+                }
+            };
+            return magnitude.checked_mul(&unit);
+        }
+        if exp.is_integer() {
+            let n = exp.re.to_i128()?;
+            let power = self.powi(u32::try_from(n.unsigned_abs()).ok()?)?;
+            return if n < 0 { Self::from(1).checked_div(&power) } else { Some(power) };
+        }
+        if self.is_zero() {
+            return exp.re.is_positive().then(|| self.clone());
+        }
+        (exp.clone() * self.ln()?).exp()
+    }
+
+    /// Principal root, exact where the parts are perfect squares. The larger part of the root is
+    /// `t = sqrt(|z|/2 + |re|/2)` and the other `|im| / 2t`, so neither is lost to cancellation nor out of range.
+    fn sqrt(&self) -> Option<Self> {
+        if self.im.is_zero() && !self.re.is_negative() {
+            return self.re.sqrt().map(Self::real);
+        }
+        let half = |n:N| n / N::from(2);
+        let large = half(self.modulus()?).checked_add(&half(self.re.abs()))?.sqrt()?;
+        let small = self.im.abs().checked_div(&large.checked_add(&large)?)?;
+        let (re,im) = if self.re.is_negative() { (small,large) } else { (large,small) };
+        Some(Self::new(re, if self.im.is_negative() { -im } else { im }))
+    }
+
+    /// `exp(re) (cos im + i sin im)`
+    fn exp(&self) -> Option<Self> {
+        if self.im.is_zero() {
+            return self.re.exp().map(Self::real);
+        }
+        let scale = self.re.exp()?;
+        Some(Self::new(scale.checked_mul(&self.im.cos()?)?, scale.checked_mul(&self.im.sin()?)?))
+    }
+
+    /// Principal logarithm, `ln|z| + arg(z) i`.
+    fn ln(&self) -> Option<Self> {
+        if self.is_positive() {
+            return self.re.ln().map(Self::real);
+        }
+        Some(Self::new(self.modulus()?.ln()?, self.arg()?))
+    }
+
+    /// `sin(re) cosh(im) + cos(re) sinh(im) i`
+    fn sin(&self) -> Option<Self> {
+        if self.im.is_zero() {
+            return self.re.sin().map(Self::real);
+        }
+        let (cosh,sinh) = Self::hyperbolic(&self.im)?;
+        Some(Self::new(self.re.sin()?.checked_mul(&cosh)?, self.re.cos()?.checked_mul(&sinh)?))
+    }
+
+    /// `cos(re) cosh(im) - sin(re) sinh(im) i`
+    fn cos(&self) -> Option<Self> {
+        if self.im.is_zero() {
+            return self.re.cos().map(Self::real);
+        }
+        let (cosh,sinh) = Self::hyperbolic(&self.im)?;
+        Some(Self::new(self.re.cos()?.checked_mul(&cosh)?, -self.re.sin()?.checked_mul(&sinh)?))
+    }
+
+    fn tan(&self) -> Option<Self> {
+        if self.im.is_zero() {
+            return self.re.tan().map(Self::real);
+        }
+        self.sin()?.checked_div(&self.cos()?)
+    }
+
+    /// Real arguments only, the complex arctangent being left out.
+    fn atan(&self) -> Option<Self> {
+        if self.im.is_zero() { self.re.atan().map(Self::real) } else { None }
+    }
+
+    fn pi() -> Self {
+        Self::real(N::pi())
+    }
+
+    fn imaginary_unit() -> Option<Self> {
+        Some(Self::i())
+    }
+
+    fn is_real(&self) -> bool {
+        self.im.is_zero()
+    }
+
+    /// Real coefficients give their real roots first, in ascending order, then the complex ones;
+    /// complex coefficients go straight to Durand–Kerner.
+    fn polynomial_roots(poly:&Polynomial<Self>) -> Result<Vec<Self>,EvalError> {
+        if poly.is_zero() {
+            return Err(EvalError::InfiniteSolutions);
+        }
+        if poly.coefs().iter().all(Self::is_real) {
+            return real_polynomial_roots(&poly.coefs().iter().map(|it| it.re.clone()).collect::<Vec<N>>());
+        }
+        let zeros = poly.coefs().iter().take_while(|it| it.is_zero()).count();
+        let mut roots = vec![Self::from(0); zeros];
+        roots.extend(durand_kerner(&poly.coefs()[zeros..])?);
+        roots.sort_by(Self::total_cmp);
+        Ok(roots)
+    }
+}
+
+/// The operators go through the checked operations, so they only fail where the result itself is out of range,
+/// as the operators of `Decimal` do; the evaluator uses the checked operations and reports that case as an error.
+macro_rules! binary_op {
+    ($op:ident, $method:ident, $assign:ident, $assign_method:ident, $checked:ident, $symbol:literal) => {
+        impl<N:Numeric> $op for Complex<N> {
+            type Output = Self;
+            fn $method(self, rhs:Self) -> Self { self.$method(&rhs) }
+        }
+        impl<N:Numeric> $op<&Complex<N>> for Complex<N> {
+            type Output = Self;
+            fn $method(self, rhs:&Self) -> Self {
+                self.$checked(rhs).unwrap_or_else(|| panic!("{} {} {} is out of range", self, $symbol, rhs))
+            }
+        }
+        impl<N:Numeric> $assign for Complex<N> {
+            fn $assign_method(&mut self, rhs:Self) { *self = self.clone().$method(&rhs) }
+        }
+        impl<N:Numeric> $assign<&Complex<N>> for Complex<N> {
+            fn $assign_method(&mut self, rhs:&Self) { *self = self.clone().$method(rhs) }
+        }
+    };
+}
+
+binary_op!(Add, add, AddAssign, add_assign, checked_add, "+");
+binary_op!(Sub, sub, SubAssign, sub_assign, checked_sub, "-");
+binary_op!(Mul, mul, MulAssign, mul_assign, checked_mul, "*");
+binary_op!(Div, div, DivAssign, div_assign, checked_div, "/");
+
+impl<N:Numeric> Neg for Complex<N> {
+    type Output = Self;
+    fn neg(self) -> Self { Complex::new(-self.re, -self.im) }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use crate::num::Num;
+    use crate::numeric::Numeric;
+    use super::Complex;
+
+    /// `(-8)^(1/3)` on the number type `N`.
+    fn cube_root<N:Numeric>() -> Complex<N> {
+        let third = Complex::<N>::from(1) / Complex::from(3);
+        Complex::<N>::from(-8).pow(&third).unwrap()
+    }
+
+    #[test]
+    fn roots_of_negatives() {
+        assert_eq!(cube_root::<Num>().to_string(), "1+1.732050807568877293527446342i");
+        let decimal = cube_root::<Decimal>();
+        assert_eq!((decimal.re.round_dp(10).to_string(), decimal.im.round_dp(10).to_string()), ("1.0000000000".to_string(), "1.7320508076".to_string()));
+        let float = cube_root::<f64>();
+        assert!((float.re - 1.0).abs() < 1e-9 && (float.im - 3f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn large_parts() {
+        let big = Decimal::from(100_000_000_000_000_000_000i128);
+        assert_eq!(Complex::new(big, big).checked_abs().unwrap().re.round_dp(2).to_string(), "141421356237309504880.17");
+        assert_eq!(Complex::new(Decimal::MAX, Decimal::MAX).checked_abs(), None);
+        let root = Complex::new(-big, Decimal::ONE).sqrt().unwrap();
+        assert_eq!((root.re.round_dp(15).normalize().to_string(), root.im.normalize().to_string()), ("0.00000000005".to_string(), "10000000000".to_string()));
+        let quotient = Complex::new(Decimal::ONE, Decimal::ONE).checked_div(&Complex::new(big, big)).unwrap();
+        assert_eq!((quotient.re.normalize().to_string(), quotient.im.is_zero()), ("0.00000000000000000001".to_string(), true));
+        assert_eq!(Complex::new(Decimal::MAX, Decimal::MAX).checked_mul(&Complex::new(Decimal::TWO, Decimal::ONE)), None);
+    }
+
+    #[test]
+    fn exact_parts() {
+        assert_eq!(Complex::new(Num::from(3), Num::from(4)).checked_abs().unwrap().to_string(), "5");
+        assert_eq!(Complex::new(Num::from(-3), Num::from(4)).sqrt().unwrap().to_string(), "1+2i");
+        assert_eq!(Complex::new(Num::from(1), Num::from(2)).checked_div(&Complex::new(Num::from(3), Num::from(-4))).unwrap().to_string(), "-0.2+0.4i");
+    }
+}
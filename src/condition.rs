@@ -0,0 +1,81 @@
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+use crate::term::{Logic, Relation, Term};
+
+impl<N:Numeric> Term<N> {
+    fn boolean(value:bool) -> Term<N> {
+        Term::Number(N::from(value as i32))
+    }
+
+    /// Truth of a condition folded to a number, anything but zero holding.
+    pub(crate) fn truth(&self) -> Option<bool> {
+        match self {
+            Term::Number(n) => Some(!n.is_zero()),
+            _ => None,
+        }
+    }
+
+    /// Whether `relation` holds between two numbers, which must be real to be ordered.
+    pub(crate) fn holds(relation:Relation, left:&N, right:&N) -> Result<bool,EvalError> {
+        if !left.is_real() || !right.is_real() {
+            return match relation {
+                Relation::Equal => Ok(left == right),
+                Relation::NotEqual => Ok(left != right),
+                _ => Err(EvalError::Unordered(left.to_string(), right.to_string())),
+            };
+        }
+        Ok(relation.holds(left.total_cmp(right)))
+    }
+
+    /// Compares already simplified terms, folding real numbers to `1` or `0`.
+    pub(crate) fn relation(relation:Relation, left:Term<N>, right:Term<N>) -> Term<N> {
+        match (&left,&right) {
+            (Term::Number(l),Term::Number(r)) => match Self::holds(relation, l, r) {
+                Ok(value) => Self::boolean(value),
+                Err(_) => Term::Compare(relation, left.into(), right.into()),
+            },
+            _ => Term::Compare(relation, left.into(), right.into()),
+        }
+    }
+
+    /// Combines already simplified conditions, deciding as soon as a known operand settles the result.
+    pub(crate) fn logic(logic:Logic, terms:Vec<Term<N>>) -> Term<N> {
+        let truths = terms.iter().map(Term::truth).collect::<Vec<Option<bool>>>();
+        match logic {
+            Logic::Not => match truths.as_slice() {
+                [Some(value)] => Self::boolean(!value),
+                _ => Term::Logic(logic, terms),
+            },
+            Logic::And | Logic::Or => {
+                // `false` settles a conjunction, `true` a disjunction.
+                let settles = logic == Logic::Or;
+                if truths.contains(&Some(settles)) {
+                    return Self::boolean(settles);
+                }
+                let undecided = terms.into_iter().zip(truths).filter(|(_,truth)| truth.is_none()).map(|(term,_)| term).collect::<Vec<Term<N>>>();
+                match undecided.len() {
+                    0 => Self::boolean(!settles),
+                    _ => Term::Logic(logic, undecided),
+                }
+            }
+        }
+    }
+
+    /// Drops the branches of an already simplified piecewise term that can not apply,
+    /// down to the value of the first branch known to hold.
+    pub(crate) fn piecewise(branches:Vec<(Term<N>,Term<N>)>, otherwise:Option<Term<N>>) -> Term<N> {
+        let mut kept = Vec::new();
+        for (cond,value) in branches {
+            match cond.truth() {
+                Some(true) if kept.is_empty() => return value,
+                Some(true) => return Term::Piecewise(kept, Some(value.into())),
+                Some(false) => {}
+                None => kept.push((cond,value)),
+            }
+        }
+        match (kept.is_empty(),otherwise) {
+            (true,Some(otherwise)) => otherwise,
+            (_,otherwise) => Term::Piecewise(kept, otherwise.map(Box::new)),
+        }
+    }
+}
@@ -1,15 +1,54 @@
 use std::collections::LinkedList;
 use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::io::Write;
 
-struct Console{
+/// Why no input could be read.
+#[derive(Debug)]
+pub enum ConsoleError {
+    Io(std::io::Error),
+    /// The input was closed, so nothing more will come.
+    Closed,
+}
+
+impl Display for ConsoleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Closed => write!(f, "Input closed"),
+        }
+    }
+}
+
+impl Error for ConsoleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Closed => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConsoleError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+pub struct Console{
     lines:Vec<String>
 }
 
-pub(crate) struct ConsoleManager {
+pub struct ConsoleManager {
     consoles:LinkedList<Console>
 }
 
+impl Default for ConsoleManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ConsoleManager {
     pub fn new() -> Self{
         Self{
@@ -21,17 +60,18 @@ impl ConsoleManager {
         self.consoles.front_mut().unwrap()
     }
 
-    pub fn join(&mut self,todo: impl Fn(&mut Console) -> ()){
+    pub fn join(&mut self,todo: impl Fn(&mut Console)){
         let mut console = Console::new();
         todo(&mut console);
         self.consoles.push_front(console);
         self.flush();
     }
 
-    pub  fn leave(&mut self){
+    pub fn leave(&mut self){
         self.consoles.pop_front();
         self.flush();
     }
+
     pub fn print(&mut self,line:impl Into<String>){
         self.head().print(line.into());
         self.flush();
@@ -53,11 +93,13 @@ impl ConsoleManager {
         }
         std::io::stdout().flush().unwrap()
     }
-    pub fn input(&self) -> Result<String,Box<dyn Error>> {
+    pub fn input(&self) -> Result<String,ConsoleError> {
         print!(">> ");
         std::io::stdout().flush()?;
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            return Err(ConsoleError::Closed);
+        }
         Ok(input.trim_end().into())
     }
 }
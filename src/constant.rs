@@ -0,0 +1,55 @@
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+
+/// A named constant, resolved to its value only when the formula is evaluated or inlined.
+pub struct Constant {
+    pub name:&'static str,
+    value:Value,
+    pub description:&'static str,
+}
+
+enum Value {
+    /// Scientific notation, exact where the definition of the SI units fixes it.
+    Measured(&'static str),
+    /// Irrational, computed by the number type to its own precision.
+    Pi,
+    Tau,
+    E,
+    Phi,
+    /// The imaginary unit, for number types that have it.
+    I,
+}
+
+pub const CONSTANTS:&[Constant] = &[
+    Constant { name: "pi", value: Value::Pi, description: "ratio of a circle's circumference to its diameter" },
+    Constant { name: "tau", value: Value::Tau, description: "2 pi" },
+    Constant { name: "e", value: Value::E, description: "base of the natural logarithm" },
+    Constant { name: "phi", value: Value::Phi, description: "golden ratio" },
+    Constant { name: "i", value: Value::I, description: "imaginary unit, i^2 = -1" },
+    Constant { name: "c", value: Value::Measured("299792458"), description: "speed of light in vacuum, m/s" },
+    Constant { name: "h", value: Value::Measured("6.62607015e-34"), description: "Planck constant, J s" },
+    Constant { name: "hbar", value: Value::Measured("1.054571817e-34"), description: "reduced Planck constant, J s" },
+    Constant { name: "G", value: Value::Measured("6.67430e-11"), description: "gravitational constant, m^3/(kg s^2)" },
+    Constant { name: "kB", value: Value::Measured("1.380649e-23"), description: "Boltzmann constant, J/K" },
+    Constant { name: "NA", value: Value::Measured("6.02214076e23"), description: "Avogadro constant, 1/mol" },
+    Constant { name: "qe", value: Value::Measured("1.602176634e-19"), description: "elementary charge, C" },
+];
+
+impl Constant {
+    pub fn find(name:&str) -> Option<&'static Constant> {
+        CONSTANTS.iter().find(|it| it.name == name)
+    }
+
+    /// Fails when the number type can not hold the value, such as `h` in 28 digit decimals.
+    pub fn value<N:Numeric>(&self) -> Result<N,EvalError> {
+        let value = match self.value {
+            Value::Measured(value) => N::parse(value),
+            Value::Pi => Some(N::pi()),
+            Value::Tau => Some(N::from(2) * N::pi()),
+            Value::E => N::from(1).exp(),
+            Value::Phi => N::from(5).sqrt().map(|it| (N::from(1) + it) / N::from(2)),
+            Value::I => N::imaginary_unit(),
+        };
+        value.ok_or_else(|| EvalError::Overflow(format!("Constant {}",self.name)))
+    }
+}
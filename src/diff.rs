@@ -0,0 +1,145 @@
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+use crate::term::{Commutative, Function, MatrixFunction, Term};
+
+impl<N:Numeric> Term<N> {
+    /// Derivative with respect to `unknown`, simplified.
+    pub fn diff(&self, unknown:&str) -> Result<Term<N>,EvalError> {
+        Ok(self.derive(unknown)?.simplify())
+    }
+
+    fn depends_on(&self, unknown:&str) -> bool {
+        self.unknowns().contains(unknown)
+    }
+
+    fn derive(&self, unknown:&str) -> Result<Term<N>,EvalError> {
+        let zero = Term::Number(N::from(0));
+        if !self.depends_on(unknown) {
+            return Ok(zero);
+        }
+        let derived = match self {
+            Term::Number(_) | Term::Unit(_) => zero,
+            Term::Unknown(_) => Term::Number(N::from(1)),
+            Term::Opposite(u) => Term::Opposite(u.derive(unknown)?.into()),
+            // (1/u)' = -u' / u^2
+            Term::MulInverse(u) => Term::product(vec![
+                Term::Number(N::from(-1)),
+                u.derive(unknown)?,
+                Term::Power(Some(u.clone()), Term::Number(N::from(-2)).into()),
+            ]),
+            // Chain rule: f(u)' = f'(u) * u'
+            Term::Function(func,u) => {
+                let outer = match func {
+                    Function::Sin => Term::Function(Function::Cos, u.clone()),
+                    Function::Cos => Term::Opposite(Term::Function(Function::Sin, u.clone()).into()),
+                    Function::Tan => Term::Power(Some(Term::Function(Function::Cos, u.clone()).into()), Term::Number(N::from(-2)).into()),
+                    Function::Ln => Term::MulInverse(u.clone()),
+                    Function::Exp => self.clone(),
+                    Function::Sqrt => Term::MulInverse(Term::product(vec![Term::Number(N::from(2)), self.clone()]).into()),
+                    Function::Abs => Term::product(vec![*u.clone(), Term::MulInverse(self.clone().into())]),
+                };
+                Term::product(vec![outer, u.derive(unknown)?])
+            }
+            Term::Chain(Commutative::Add,terms) => {
+                let mut derived = Vec::new();
+                for term in terms {
+                    derived.push(term.derive(unknown)?);
+                }
+                Term::Chain(Commutative::Add, derived.into())
+            }
+            // Product rule: each factor derived in turn, times all the others.
+            Term::Chain(Commutative::Multiply,factors) => {
+                let mut derived = Vec::new();
+                for (i,factor) in factors.iter().enumerate() {
+                    if !factor.depends_on(unknown) {
+                        continue;
+                    }
+                    let mut product = factors.clone();
+                    product[i] = factor.derive(unknown)?;
+                    derived.push(Term::Chain(Commutative::Multiply, product));
+                }
+                Term::Chain(Commutative::Add, derived.into())
+            }
+            Term::Power(Some(u),v) => {
+                let power = self.clone();
+                match (u.depends_on(unknown),v.depends_on(unknown)) {
+                    // (u^c)' = c * u^(c-1) * u'
+                    (true,false) => Term::product(vec![
+                        *v.clone(),
+                        Term::Power(Some(u.clone()), Term::Chain(Commutative::Add, [*v.clone(),Term::Number(N::from(-1))].into()).into()),
+                        u.derive(unknown)?,
+                    ]),
+                    // (c^v)' = c^v * ln(c) * v'
+                    (false,_) => Term::product(vec![power, Term::Function(Function::Ln, u.clone()), v.derive(unknown)?]),
+                    // (u^v)' = u^v * (v' * ln(u) + v * u' / u)
+                    (true,true) => Term::product(vec![power, Term::Chain(Commutative::Add, [
+                        Term::product(vec![v.derive(unknown)?, Term::Function(Function::Ln, u.clone())]),
+                        Term::product(vec![*v.clone(), u.derive(unknown)?, Term::MulInverse(u.clone())]),
+                    ].into())]),
+                }
+            }
+            Term::Matrix(rows) => Term::Matrix(rows.iter().map(|row| row.iter().map(|it| it.derive(unknown)).collect()).collect::<Result<_,_>>()?),
+            // Product rule again, the factors staying in their order.
+            Term::MatrixProduct(factors) => {
+                let mut derived = Vec::new();
+                for (i,factor) in factors.iter().enumerate() {
+                    if !factor.depends_on(unknown) {
+                        continue;
+                    }
+                    let mut product = factors.clone();
+                    product[i] = factor.derive(unknown)?;
+                    derived.push(Term::MatrixProduct(product));
+                }
+                Term::Chain(Commutative::Add, derived.into())
+            }
+            // Conditions only change value where they flip, so they are taken as constant.
+            Term::Compare(..) | Term::Logic(..) => zero,
+            Term::Piecewise(branches,otherwise) => {
+                let mut derived = Vec::new();
+                for (cond,value) in branches {
+                    derived.push((cond.clone(), value.derive(unknown)?));
+                }
+                Term::Piecewise(derived, otherwise.as_deref().map(|it| it.derive(unknown)).transpose()?.map(Box::new))
+            }
+            Term::MatrixFunction(MatrixFunction::Transpose,args) => Term::MatrixFunction(MatrixFunction::Transpose, vec![args[0].derive(unknown)?]),
+            Term::MatrixFunction(func,_) => return Err(EvalError::NotDifferentiable(func.to_string())),
+            Term::Call(name,_) => return Err(EvalError::UndefinedFunction(name.clone())),
+            Term::Power(None,_) => return Err(EvalError::MissingBase),
+        };
+        Ok(derived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::EvalError;
+    use crate::testing::{formula, N};
+
+    fn diff(s:&str) -> Result<String,EvalError> {
+        Ok(formula::<N>(s).diff("x")?.to_string())
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(diff("3").unwrap(), "0");
+        assert_eq!(diff("y").unwrap(), "0");
+        assert_eq!(diff("x").unwrap(), "1");
+        assert_eq!(diff("x^3 + 2*x + y").unwrap(), "3*x^2 + 2");
+        assert_eq!(diff("x*y").unwrap(), "y");
+        assert_eq!(diff("-x^2").unwrap(), "-2*x");
+        assert_eq!(diff("1/x").unwrap(), "-1 / x^2");
+    }
+
+    #[test]
+    fn chain_rule() {
+        assert_eq!(diff("sin(2*x)").unwrap(), "2*cos(2*x)");
+        assert_eq!(diff("ln(x)").unwrap(), "x^-1");
+        assert_eq!(diff("exp(x^2)").unwrap(), "2*x*exp(x^2)");
+    }
+
+    #[test]
+    fn variable_exponents() {
+        assert_eq!(diff("2^x").unwrap(), "0.6931471805599453094172321215*2^x");
+        assert_eq!(diff("x^x").unwrap(), "x^x*(ln(x) + 1)");
+    }
+}
@@ -0,0 +1,230 @@
+use crate::num::{self, Num};
+
+/// Largest magnitude `exp` accepts, its result having too many digits to hold beyond it.
+const EXP_LIMIT:i64 = 100_000;
+
+impl Num {
+    /// π to the working precision.
+    pub fn pi() -> Num {
+        Self::pi_to(num::working_digits())
+    }
+
+    /// Machin's formula, `π = 16 atan(1/5) - 4 atan(1/239)`.
+    fn pi_to(digits:u32) -> Num {
+        (Num::from(16) * atan_inverse(5, digits + 2) - Num::from(4) * atan_inverse(239, digits + 2)).round_sig(digits)
+    }
+
+    pub fn exp(&self) -> Option<Num> {
+        if self.is_zero() {
+            return Some(Num::from(1));
+        }
+        if self.abs() > Num::from(EXP_LIMIT) {
+            return None;
+        }
+        // exp(x) = exp(x / 2^k)^(2^k), the series converging quickly once the argument is small.
+        let mut reduced = self.clone();
+        let mut halvings = 0;
+        while reduced.abs() > Num::new(1, 256) {
+            reduced /= Num::from(2);
+            halvings += 1;
+        }
+        // Every squaring doubles the relative error, so a digit is added for every three of them.
+        let digits = num::working_digits() + halvings / 3 + 1;
+        let reduced = reduced.round_sig(digits);
+        let eps = Num::ten_pow(-(digits as i32));
+        let mut sum = Num::from(1);
+        let mut term = Num::from(1);
+        let mut n = 0;
+        while term.abs() >= eps {
+            n += 1;
+            term = (term * &reduced / Num::from(n)).round_sig(digits);
+            sum += &term;
+        }
+        for _ in 0..halvings {
+            sum = (&sum * &sum).round_sig(digits);
+        }
+        Some(sum.round_sig(num::working_digits()))
+    }
+
+    pub fn ln(&self) -> Option<Num> {
+        if !self.is_positive() {
+            return None;
+        }
+        if *self == Num::from(1) {
+            return Some(Num::from(0));
+        }
+        // x = y * 2^k with y close to 1, so ln(x) = ln(y) + k ln(2).
+        let mut y = self.clone();
+        let mut k = 0i64;
+        while y > Num::new(4, 3) {
+            y /= Num::from(2);
+            k += 1;
+        }
+        while y < Num::new(2, 3) {
+            y *= Num::from(2);
+            k -= 1;
+        }
+        let digits = num::working_digits() + k.unsigned_abs().to_string().len() as u32;
+        let y = y.round_sig(digits);
+        let ln_y = ln_ratio(&((&y - Num::from(1)) / (&y + Num::from(1))), digits);
+        let ln_2 = ln_ratio(&Num::new(1, 3), digits);
+        Some((ln_y + ln_2 * Num::from(k)).round_sig(num::working_digits()))
+    }
+
+    pub fn sin(&self) -> Num {
+        if self.is_zero() {
+            return self.clone();
+        }
+        let (angle,digits) = self.reduce_angle();
+        let square = &angle * &angle;
+        let eps = Num::ten_pow(-(digits as i32));
+        let mut sum = angle.clone();
+        let mut term = angle;
+        let mut n = 1;
+        while term.abs() >= eps {
+            term = (-term * &square / Num::from((2 * n) * (2 * n + 1))).round_dp(digits as i32);
+            sum += &term;
+            n += 1;
+        }
+        self.without_residue(sum.round_sig(num::working_digits()))
+    }
+
+    pub fn cos(&self) -> Num {
+        if self.is_zero() {
+            return Num::from(1);
+        }
+        let (angle,digits) = self.reduce_angle();
+        let square = &angle * &angle;
+        let eps = Num::ten_pow(-(digits as i32));
+        let mut sum = Num::from(1);
+        let mut term = Num::from(1);
+        let mut n = 1;
+        while term.abs() >= eps {
+            term = (-term * &square / Num::from((2 * n - 1) * (2 * n))).round_dp(digits as i32);
+            sum += &term;
+            n += 1;
+        }
+        self.without_residue(sum.round_sig(num::working_digits()))
+    }
+
+    /// `None` at the poles, where the cosine vanishes to the working precision.
+    pub fn tan(&self) -> Option<Num> {
+        self.sin().checked_div(&self.cos())
+    }
+
+    pub fn atan(&self) -> Num {
+        if self.is_zero() {
+            return self.clone();
+        }
+        let digits = num::working_digits() + 2;
+        // atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))), halving the angle until the series converges quickly.
+        let mut x = self.clone();
+        let mut halvings = 0;
+        while x.abs() > Num::new(1, 8) {
+            let hypot = (Num::from(1) + &x * &x).sqrt().expect("1 + x^2 is positive");
+            x = (&x / (Num::from(1) + hypot)).round_sig(digits);
+            halvings += 1;
+        }
+        let square = &x * &x;
+        let eps = Num::ten_pow(-(digits as i32));
+        let mut sum = x.clone();
+        let mut power = x;
+        let mut n = 0;
+        loop {
+            power = (-power * &square).round_sig(digits);
+            n += 1;
+            let term = &power / Num::from(2 * n + 1);
+            if term.abs() < eps {
+                break;
+            }
+            sum += term;
+        }
+        (sum * Num::from(1 << halvings)).round_sig(num::working_digits())
+    }
+
+    /// `value` as zero when it is below the last working digit of this argument,
+    /// so `sin(π)` is `0` rather than the error left in the digits of `π`.
+    fn without_residue(&self, value:Num) -> Num {
+        if value.abs() < Num::ten_pow(self.magnitude() + 1 - num::working_digits() as i32) { Num::from(0) } else { value }
+    }
+
+    /// The angle brought into `[-π, π]` by whole turns, and the places to carry through the series.
+    fn reduce_angle(&self) -> (Num,u32) {
+        let digits = num::working_digits() + 2;
+        // Large angles need as many more digits of π as they have before the point.
+        let turn = Num::from(2) * Self::pi_to(digits + self.magnitude().max(0) as u32);
+        let turns = (self / &turn).round();
+        ((self - turns * turn).round_dp(digits as i32), digits)
+    }
+}
+
+/// `ln((1 + z) / (1 - z)) = 2 (z + z^3/3 + z^5/5 + …)`, for small `z`.
+fn ln_ratio(z:&Num, digits:u32) -> Num {
+    let eps = z.abs() * Num::ten_pow(-(digits as i32));
+    let square = z * z;
+    let mut sum = Num::from(0);
+    let mut power = z.clone();
+    let mut n = 0;
+    loop {
+        let term = (&power / Num::from(2 * n + 1)).round_sig(digits);
+        if term.abs() <= eps {
+            break;
+        }
+        sum += term;
+        power = (power * &square).round_sig(digits);
+        n += 1;
+    }
+    Num::from(2) * sum
+}
+
+/// `atan(1/n) = 1/n - 1/(3 n^3) + 1/(5 n^5) - …`
+fn atan_inverse(n:i64, digits:u32) -> Num {
+    let eps = Num::ten_pow(-(digits as i32));
+    let square = Num::from(n * n);
+    let mut sum = Num::from(0);
+    let mut power = Num::new(1, n);
+    let mut k = 0;
+    loop {
+        let term = &power / Num::from(2 * k + 1);
+        if term < eps {
+            break;
+        }
+        if k % 2 == 0 { sum += term } else { sum -= term }
+        power /= &square;
+        k += 1;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Num;
+    use crate::term::{Function, Term};
+    use crate::testing::{formula, value, N};
+
+    #[test]
+    fn residues() {
+        assert_eq!(Num::pi().sin(), Num::from(0));
+        assert_eq!((Num::pi() * Num::from(3)).sin(), Num::from(0));
+        assert_eq!((Num::pi() / Num::from(2)).cos(), Num::from(0));
+        assert_eq!((Num::pi() / Num::from(2)).tan(), None);
+        let tiny = Num::ten_pow(-40);
+        assert_eq!(tiny.sin(), tiny);
+    }
+
+    #[test]
+    fn calls() {
+        assert!(matches!(formula::<N>("sin(x)"), Term::Function(Function::Sin,_)));
+        assert!(matches!(formula::<N>("sin*x"), Term::Chain(..)));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(value::<N>("sin(0) + cos(0)", &[]).unwrap(), "1");
+        assert_eq!(value::<N>("sqrt(x)", &[("x",16)]).unwrap(), "4");
+        assert_eq!(value::<N>("abs(x)", &[("x",-3)]).unwrap(), "3");
+        assert_eq!(value::<N>("ln(exp(x))", &[("x",2)]).unwrap(), "2");
+        assert_eq!(value::<N>("exp(1)", &[]).unwrap(), "2.718281828459045235360287471");
+        assert_eq!(value::<N>("tan(pi/4)", &[]).unwrap(), "1");
+    }
+}
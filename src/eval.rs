@@ -1,18 +1,141 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::error::Error;
-use std::ops::{Div, Neg};
-use rust_decimal::MathematicalOps;
-use rust_decimal_macros::dec;
+use std::fmt::{Display, Formatter};
 use crate::console::ConsoleManager;
-use crate::term::{Commutative, Equation,  Term};
+use crate::constant::Constant;
+use crate::expand::{EXPANSION_LIMIT, EXPANSION_TERMS};
+use crate::numeric::{Numeric, Ordered};
+use crate::poly::Polynomial;
+use crate::system;
+use crate::matrix::{self, MatrixError};
+use crate::term::{Commutative, Equation, MatrixFunction, Term};
+use crate::unit::UnitError;
 
+/// Why a formula could not be evaluated or solved.
+#[derive(Debug)]
+pub enum EvalError {
+    /// A variable with no value to evaluate it with, nor a constant of that name.
+    UnknownVariable(String),
+    UndefinedFunction(String),
+    /// A call with the wrong number of arguments.
+    Arity{function:String, expected:usize, found:usize},
+    RepeatedParameter{function:String, param:String},
+    /// A function calling itself more deeply than the depth limit allows.
+    RecursionLimit{function:String, limit:usize},
+    DivisionByZero,
+    /// A number too large for the number type, or an exponent too large to expand.
+    Overflow(String),
+    /// A power or function taken outside of its domain, such as `ln(0)`.
+    Undefined(String),
+    /// Complex numbers compared by size.
+    Unordered(String,String),
+    /// A formula left with something that is not a number, such as a call nothing defines.
+    Unevaluated(String),
+    /// An equation missing its right side.
+    InvalidEquation,
+    /// A binding that is not `name = formula`.
+    InvalidBinding,
+    NotAnEquation,
+    TooManyUnknowns(usize),
+    /// An equation that is not a polynomial in its unknown, with the reason.
+    Unsolvable(String),
+    FractionalExponent(String),
+    NotLinear(String),
+    /// A failure in one equation of a system, numbered from 1.
+    InEquation(usize,Box<EvalError>),
+    NoSolution,
+    NoRealSolution,
+    InfiniteSolutions,
+    /// `solve(A, b)` of a system with free unknowns: the notes on its equations, then the values in the free unknowns.
+    DependentSystem(Vec<String>),
+    /// `solve(A, b)` of a system no vector satisfies, with the equations that contradict each other.
+    InconsistentSystem(Vec<String>),
+    /// A piecewise term with no branch holding and no otherwise value.
+    NoBranch,
+    /// A power whose base was left out.
+    MissingBase,
+    NotDifferentiable(String),
+    NotIntegrable{term:String, unknown:String},
+    /// A term to factor that is not a polynomial in the unknown.
+    NotPolynomial(String),
+    ComplexCoefficients,
+    TooLargeToFactor,
+    /// A power of a sum above `EXPANSION_LIMIT`, or an expansion of more than `EXPANSION_TERMS` summands.
+    TooLargeToExpand(String),
+    Matrix(MatrixError),
+    Units(UnitError),
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownVariable(name) => write!(f, "Unknown variable {}", name),
+            Self::UndefinedFunction(name) => write!(f, "Undefined function {}", name),
+            Self::Arity{function,expected,found} => write!(f, "{} expects {} argument(s), got {}", function, expected, found),
+            Self::RepeatedParameter{function,param} => write!(f, "Parameter {} of {} is repeated", param, function),
+            Self::RecursionLimit{function,limit} => write!(f, "Calls to {} nest deeper than the limit of {}, raised with `depth <calls>`", function, limit),
+            Self::DivisionByZero => write!(f, "Division by zero!"),
+            Self::Overflow(what) => write!(f, "{} is out of range", what),
+            Self::Undefined(what) => write!(f, "{} is undefined", what),
+            Self::Unordered(left,right) => write!(f, "Can not order {} and {}", left, right),
+            Self::Unevaluated(term) => write!(f, "Can not evaluate {}", term),
+            Self::InvalidEquation => write!(f, "Invalid Equation"),
+            Self::InvalidBinding => write!(f, "Invalid Unknown Equation"),
+            Self::NotAnEquation => write!(f, "Not an equation!"),
+            Self::TooManyUnknowns(count) => write!(f, "Expect at most one unknown, found {}", count),
+            Self::Unsolvable(reason) => write!(f, "{}", reason),
+            Self::FractionalExponent(exp) => write!(f, "Unsupported fractional exponent {}", exp),
+            Self::NotLinear(term) => write!(f, "Not linear: {}", term),
+            Self::InEquation(index,err) => write!(f, "Equation {}: {}", index, err),
+            Self::NoSolution => write!(f, "No solution!"),
+            Self::NoRealSolution => write!(f, "No real solution!"),
+            Self::InfiniteSolutions => write!(f, "Infinite solutions!"),
+            Self::DependentSystem(lines) => write!(f, "Infinite solutions: {}", lines.join("; ")),
+            Self::InconsistentSystem(lines) => write!(f, "No solution: {}", lines.join("; ")),
+            Self::NoBranch => write!(f, "No branch of the piecewise term applies!"),
+            Self::MissingBase => write!(f, "Power without base!"),
+            Self::NotDifferentiable(what) => write!(f, "Can not differentiate {}", what),
+            Self::NotIntegrable{term,unknown} => write!(f, "Can not integrate {} with respect to {}", term, unknown),
+            Self::NotPolynomial(unknown) => write!(f, "Not a polynomial in {}", unknown),
+            Self::ComplexCoefficients => write!(f, "Can only factor polynomials with real coefficients"),
+            Self::TooLargeToFactor => write!(f, "Coefficients too large to factor!"),
+            Self::TooLargeToExpand(term) => write!(f, "Can not expand {}, expansions stop at power {} or {} terms", term, EXPANSION_LIMIT, EXPANSION_TERMS),
+            Self::Matrix(err) => write!(f, "{}", err),
+            Self::Units(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for EvalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InEquation(_,err) => Some(err.as_ref()),
+            Self::Matrix(err) => Some(err),
+            Self::Units(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
-pub struct Evaluator {
-    pub(crate) formula:Term,
+impl From<MatrixError> for EvalError {
+    fn from(err: MatrixError) -> Self {
+        Self::Matrix(err)
+    }
 }
 
-impl Evaluator {
-    fn trans_pos(mut equation:Equation) -> Result<Term,Box<dyn Error>>{
+impl From<UnitError> for EvalError {
+    fn from(err: UnitError) -> Self {
+        Self::Units(err)
+    }
+}
+
+pub struct Evaluator<N> {
+    pub formula:Term<N>,
+    pub equation:bool,
+}
+
+impl<N:Numeric> Evaluator<N> {
+    fn trans_pos(equation:Equation<N>) -> Result<Term<N>,EvalError>{
         match equation {
             Equation(Some(mut left), Some(right)) => {
                 match left {
@@ -27,84 +150,212 @@ impl Evaluator {
                 Ok(left)
             }
             Equation(None, Some(right)) => Ok(right),
-            _ => Err("Invalid Equation".into())
+            _ => Err(EvalError::InvalidEquation)
         }
     }
-    fn process(equation:Equation) -> Result<Term,Box<dyn Error>>{
-        let mut left = Self::trans_pos(equation)?;
-        Self::const_expr(&mut left, None)?;
-        return Ok(left);
+    fn process(equation:Equation<N>) -> Result<Term<N>,EvalError>{
+        Ok(Self::trans_pos(equation)?.simplify())
     }
-    pub fn new(equation:Equation) -> Result<Self,Box<dyn Error>> {
+    pub fn new(equation:Equation<N>) -> Result<Self,EvalError> {
+        let is_equation = equation.0.is_some();
+        let formula = Self::process(equation)?;
+        formula.dimension()?;
         Ok(Self {
-            formula: Self::process(equation)?
+            formula,
+            equation: is_equation,
         })
     }
 
-    fn get(name:&str,context:&HashMap<String,Term>) -> Result<Term,Box<dyn Error>> {
-        Ok(context.get(name).ok_or(format!("Unknown variable {}",name))?.clone())
-    }
-
-    fn const_expr(term:&mut Term, context:Option<&HashMap<String,Term>>) -> Result<Option<Term>,Box<dyn Error>> {
+    fn const_expr(term:&mut Term<N>, context:Option<&HashMap<String,Term<N>>>) -> Result<Option<Term<N>>,EvalError> {
         match term {
             Term::Chain(com,terms) => {
                 for term in terms.iter_mut() {
                     Self::const_expr(term, context)?;
                 }
-                let mut result = match com {
-                    Commutative::Add => dec!(0),
-                    Commutative::Multiply => dec!(1)
+                let identity = match com {
+                    Commutative::Add => N::from(0),
+                    Commutative::Multiply => N::from(1)
                 };
+                let mut result = identity.clone();
                 let mut count = 0;
                 let len = terms.len();
                 while let Some(term) = terms.pop_front() {
                     count += 1;
                     match term {
                         Term::Number(n) => {
-                            match com {
-                                Commutative::Add => result += n,
-                                Commutative::Multiply => result *= n,
+                            result = match com {
+                                Commutative::Add => result.checked_add(&n).ok_or_else(|| EvalError::Overflow(format!("{} + {}",result,n)))?,
+                                Commutative::Multiply => result.checked_mul(&n).ok_or_else(|| EvalError::Overflow(format!("{} * {}",result,n)))?,
                             }
                         },
                         _ => terms.push_back(term)
                     }
                     if count >= len { break  }
                 }
-                if terms.is_empty() {
+                let has_matrix = terms.iter().any(|it| matches!(it,Term::Matrix(_)));
+                if !has_matrix && (terms.is_empty() || (*com == Commutative::Multiply && result.is_zero())) {
                     *term = Term::Number(result);
-                    if context.is_some(){ return Ok(Some(Term::Number(result))); }
-                } else if result != dec!(0) {
-                    terms.push_back(Term::Number(result));
+                    if context.is_some(){ return Ok(Some(term.clone())); }
+                } else {
+                    if result != identity {
+                        terms.push_back(Term::Number(result));
+                    }
+                    if has_matrix {
+                        *term = Term::Matrix(Self::element_wise(com, terms, context)?);
+                    }
                 }
             }
             Term::Power(Some( base),exp) => {
                 Self::const_expr(base, context)?;
                 Self::const_expr(exp, context)?;
-                match (base.as_ref(),exp.as_ref()) {
+                let power = |base:&Term<N>,exp:&Term<N>| Term::Power(Some(base.clone().into()), exp.clone().into());
+                let rows = match (base.as_ref(),exp.as_ref()) {
                     (Term::Number(base),Term::Number(exp)) => {
-                        *term = Term::Number(base.powd(*exp));
-                    },
-                    _ => {}
+                        let text = || power(&Term::Number(base.clone()), &Term::Number(exp.clone())).to_string();
+                        *term = Term::Number(base.pow(exp).ok_or_else(|| match () {
+                            _ if base.is_zero() && exp.is_negative() => EvalError::DivisionByZero,
+                            // Integer powers of other bases are defined, so only their size can fail.
+                            _ if exp.is_integer() => EvalError::Overflow(text()),
+                            _ => EvalError::Undefined(text()),
+                        })?);
+                        None
+                    }
+                    (Term::Matrix(bases),Term::Matrix(exps)) => Some(matrix::zip(bases, exps, power)?),
+                    (Term::Matrix(bases),exp) => Some(matrix::map(bases, |base| power(base, exp))),
+                    (base,Term::Matrix(exps)) => Some(matrix::map(exps, |exp| power(base, exp))),
+                    _ => None,
+                };
+                if let Some(rows) = rows {
+                    *term = Term::Matrix(Self::fold_entries(rows, context)?);
                 }
             }
             Term::Opposite(b) => {
                 Self::const_expr(b, context)?;
                 match b.as_ref() {
-                    Term::Number(n) => *term = Term::Number(n.neg()),
+                    Term::Number(n) => *term = Term::Number(-n.clone()),
+                    Term::Matrix(rows) => *term = Term::Matrix(Self::fold_entries(matrix::map(rows, |it| Term::Opposite(it.clone().into())), context)?),
                     _ => {}
                 }
             }
             Term::MulInverse(b) => {
                 Self::const_expr(b, context)?;
                 match b.as_ref() {
-                    Term::Number(n) => *term = Term::Number(dec!(1).div(n)),
+                    Term::Number(n) => *term = Term::Number(N::from(1).checked_div(n).ok_or(EvalError::DivisionByZero)?),
+                    Term::Matrix(rows) => *term = Term::Matrix(Self::fold_entries(matrix::map(rows, |it| Term::MulInverse(it.clone().into())), context)?),
+                    _ => {}
+                }
+            }
+            Term::Function(func,b) => {
+                Self::const_expr(b, context)?;
+                let func = *func;
+                match (func,b.as_ref()) {
+                    (_,Term::Number(n)) => *term = Term::Number(func.apply(n).ok_or_else(|| EvalError::Undefined(format!("{}({})",func,n)))?),
+                    (_,Term::Matrix(rows)) => *term = Term::Matrix(Self::fold_entries(matrix::map(rows, |it| Term::Function(func, it.clone().into())), context)?),
                     _ => {}
                 }
             }
+            Term::Compare(relation,left,right) => {
+                Self::const_expr(left, context)?;
+                Self::const_expr(right, context)?;
+                let operands = match (left.as_ref(),right.as_ref()) {
+                    (Term::Number(l),Term::Number(r)) => Some((l.clone(),r.clone())),
+                    // Quantities of the same dimension are compared through their difference in base units.
+                    _ => Term::sum(vec![
+                        left.to_base_units()?.simplify(),
+                        Term::product(vec![Term::Number(N::from(-1)), right.to_base_units()?.simplify()]),
+                    ]).magnitude().map(|difference| (difference,N::from(0))),
+                };
+                if let Some((l,r)) = operands {
+                    *term = Term::Number(N::from(Term::holds(*relation, &l, &r)? as i32));
+                }
+            }
+            Term::Logic(logic,terms) => {
+                for term in terms.iter_mut() {
+                    Self::const_expr(term, context)?;
+                }
+                *term = Term::logic(*logic, std::mem::take(terms));
+            }
+            // Conditions are tried in order and only the value of the branch taken is evaluated,
+            // so the others may be undefined there.
+            Term::Piecewise(branches,otherwise) => {
+                let mut taken = None;
+                for (cond,value) in branches.iter_mut() {
+                    Self::const_expr(cond, context)?;
+                    match cond.truth() {
+                        Some(true) => {
+                            taken = Some(std::mem::replace(value, Term::Number(N::from(0))));
+                            break;
+                        }
+                        Some(false) => {}
+                        None => return Ok(context.map(|_| term.clone())),
+                    }
+                }
+                let mut taken = match (taken,otherwise.take()) {
+                    (Some(value),_) => value,
+                    (None,Some(otherwise)) => *otherwise,
+                    (None,None) => return Err(EvalError::NoBranch),
+                };
+                Self::const_expr(&mut taken, context)?;
+                *term = taken;
+            }
+            // Neighbouring matrices are multiplied in the order written, numbers scale the result.
+            Term::MatrixProduct(factors) => {
+                let mut coef = N::from(1);
+                let mut folded:VecDeque<Term<N>> = VecDeque::new();
+                for mut factor in factors.drain(..) {
+                    Self::const_expr(&mut factor, context)?;
+                    match factor {
+                        Term::Number(n) => coef = coef.checked_mul(&n).ok_or_else(|| EvalError::Overflow(format!("{} * {}",coef,n)))?,
+                        Term::Matrix(right) if matches!(folded.back(),Some(Term::Matrix(_))) => {
+                            let Some(Term::Matrix(left)) = folded.pop_back() else { unreachable!() };
+                            folded.push_back(Term::Matrix(Self::fold_entries(matrix::multiply(&left, &right)?, context)?));
+                        }
+                        _ => folded.push_back(factor),
+                    }
+                }
+                *term = match folded.len() {
+                    0 => Term::Number(coef),
+                    1 if coef == N::from(1) => folded.pop_back().unwrap(),
+                    1 if matches!(folded.back(),Some(Term::Matrix(_))) => {
+                        let Some(Term::Matrix(rows)) = folded.pop_back() else { unreachable!() };
+                        Term::Matrix(Self::fold_entries(matrix::map(&rows, |it| Term::Chain(Commutative::Multiply, [Term::Number(coef.clone()), it.clone()].into())), context)?)
+                    }
+                    _ => {
+                        if coef != N::from(1) {
+                            folded.push_front(Term::Number(coef));
+                        }
+                        Term::MatrixProduct(folded)
+                    }
+                };
+            }
             Term::Unknown(name) => {
                 if let Some(context) = context{
-                    let Ok(num) = Self::get(name,context) else{ return Ok(None) };
-                    *term = num;
+                    match (context.get(name),Constant::find(name)) {
+                        (Some(value),_) => *term = value.clone(),
+                        (None,Some(constant)) => *term = Term::Number(constant.value()?),
+                        (None,None) => return Ok(None),
+                    }
+                }
+            }
+            Term::MatrixFunction(func,args) => {
+                for arg in args.iter_mut() {
+                    Self::const_expr(arg, context)?;
+                }
+                if args.iter().any(|it| matches!(it,Term::Number(_))) {
+                    return Err(MatrixError::NotMatrix(Term::MatrixFunction(*func, args.clone()).to_string()).into());
+                }
+                match (*func,args.as_slice()) {
+                    (MatrixFunction::Transpose,[Term::Matrix(rows)]) => *term = Term::Matrix(matrix::transpose(rows)),
+                    (MatrixFunction::Det,[Term::Matrix(rows)]) => if let Some(det) = matrix::det(rows)? {
+                        *term = Term::Number(det);
+                    },
+                    (MatrixFunction::Inverse,[Term::Matrix(rows)]) => if let Some(inverse) = matrix::inverse(rows)? {
+                        *term = Term::Matrix(inverse);
+                    },
+                    (MatrixFunction::Solve,[Term::Matrix(a),Term::Matrix(b)]) => if let Some(solution) = system::solve_matrix(a, b)? {
+                        *term = Term::Matrix(solution);
+                    },
+                    _ => {}
                 }
             }
             _ => {}
@@ -112,76 +363,215 @@ impl Evaluator {
 
         if context.is_none() { Ok(None) }
         else {
-            return Ok(Some(term.clone()));
+            Ok(Some(term.clone()))
         }
     }
 
-    pub fn inline(&mut self,context:&HashMap<String,Term>) -> Result<(),Box<dyn Error>> {
+    /// Folds a chain holding matrices entry by entry: matrices are combined element-wise,
+    /// and every other term is added to or multiplies each entry.
+    fn element_wise(com:&Commutative, terms:&VecDeque<Term<N>>, context:Option<&HashMap<String,Term<N>>>) -> Result<matrix::Rows<N>,EvalError> {
+        let chain = |entries:Vec<Term<N>>| Term::Chain(com.clone(), entries.into());
+        let mut rows:Option<matrix::Rows<N>> = None;
+        let mut others = Vec::new();
+        for term in terms {
+            match (term,&rows) {
+                (Term::Matrix(matrix),None) => rows = Some(matrix.clone()),
+                (Term::Matrix(matrix),Some(acc)) => rows = Some(matrix::zip(acc, matrix, |a,b| chain(vec![a.clone(), b.clone()]))?),
+                _ => others.push(term.clone()),
+            }
+        }
+        let rows = rows.expect("element-wise chains hold a matrix");
+        let rows = if others.is_empty() { rows } else {
+            matrix::map(&rows, |it| chain([it.clone()].into_iter().chain(others.iter().cloned()).collect()))
+        };
+        Self::fold_entries(rows, context)
+    }
+
+    fn fold_entries(mut rows:matrix::Rows<N>, context:Option<&HashMap<String,Term<N>>>) -> Result<matrix::Rows<N>,EvalError> {
+        for entry in rows.iter_mut().flatten() {
+            Self::const_expr(entry, context)?;
+        }
+        Ok(rows)
+    }
+
+    pub fn inline(&mut self,context:&HashMap<String,Term<N>>) -> Result<(),EvalError> {
         Self::const_expr(&mut self.formula, Some(context))?;
+        self.formula.dimension()?;
+        self.formula = self.formula.to_base_units()?.simplify();
         Ok(())
     }
-    pub fn eval(&self,context:&HashMap<String,Term>) -> Result<Term,Box<dyn Error>> {
+    pub fn expand(&mut self) -> Result<(),EvalError> {
+        self.formula = self.formula.expand()?;
+        Ok(())
+    }
+
+    pub fn diff(&mut self, unknown:&str) -> Result<(),EvalError> {
+        self.formula = self.formula.diff(unknown)?;
+        Ok(())
+    }
+
+    pub fn integrate(&mut self, unknown:&str) -> Result<(),EvalError> {
+        self.formula = self.formula.integrate(unknown)?;
+        Ok(())
+    }
+
+    /// Definite integral, the antiderivative evaluated at both bounds within `context`.
+    /// The bounds are evaluated first, so a bound such as `pi` is a number once substituted.
+    pub fn integrate_between(&self, unknown:&str, low:Term<N>, high:Term<N>, context:&HashMap<String,Term<N>>) -> Result<Term<N>,EvalError> {
+        let antiderivative = Evaluator { formula: self.formula.integrate(unknown)?, equation: false };
+        let bound = |term:Term<N>| Evaluator { formula: term.simplify(), equation: false }.eval(context);
+        let (low,high) = (bound(low)?,bound(high)?);
+        let mut context = context.clone();
+        context.insert(unknown.to_string(), high);
+        let high = antiderivative.eval(&context)?;
+        context.insert(unknown.to_string(), low);
+        let low = antiderivative.eval(&context)?;
+        Ok(Term::Chain(Commutative::Add, [high,Term::Opposite(low.into())].into()).simplify())
+    }
+
+    pub fn factor(&mut self) -> Result<(),EvalError> {
+        self.formula = self.formula.factor()?;
+        Ok(())
+    }
+
+    pub fn eval(&self,context:&HashMap<String,Term<N>>) -> Result<Term<N>,EvalError> {
         let mut formula = self.formula.clone();
-        Self::const_expr(&mut formula, Some(context))?.ok_or(format!("Failed to eval with {:?}", context).into())
+        let result = Self::const_expr(&mut formula, Some(context))?;
+        // A name left over is reported rather than kept in the result, so callers can ask for its value.
+        if let Some(missing) = formula.unknowns().into_iter().find(|name| !context.contains_key(name)) {
+            return Err(EvalError::UnknownVariable(missing));
+        }
+        let result = result.ok_or_else(|| EvalError::Unevaluated(self.formula.to_string()))?;
+        result.dimension()?;
+        Ok(result.to_base_units()?.simplify())
     }
 
-    /**
-    fn get_unknown_expr(term:&Term) -> Result<Num,Box<dyn Error>> {
-        let ok = match term {
-            Term::Unknown(_) => dec!(1),
-            Term::Opposite(b) => Self::get_unknown_expr(b)?,
-            Term::MulInverse(b) => Self::get_unknown_expr(b)?.neg(),
+    /// Collects `term` as a polynomial in `unknown`, mapping each exponent to its coefficient.
+    pub(crate) fn combine(term:&Term<N>, unknown:&str) -> Result<BTreeMap<Ordered<N>,N>,EvalError> {
+        let mut result = BTreeMap::new();
+        match term {
+            Term::Number(n) => { result.insert(Ordered(N::from(0)), n.clone()); }
+            Term::Unknown(name) => {
+                if name == unknown {
+                    result.insert(Ordered(N::from(1)), N::from(1));
+                } else if let Some(constant) = Constant::find(name) {
+                    result.insert(Ordered(N::from(0)), constant.value()?);
+                } else {
+                    return Err(EvalError::UnknownVariable(name.clone()));
+                }
+            }
+            Term::Opposite(b) => {
+                result = Self::combine(b, unknown)?;
+                result.values_mut().for_each(|coef| *coef = -coef.clone());
+            }
+            Term::MulInverse(b) => {
+                let inner = Self::combine(b, unknown)?;
+                let Some((exp,coef)) = Self::single(&inner) else {
+                    return Err(EvalError::Unsolvable(format!("Can not divide by {}",b)));
+                };
+                if coef.is_zero() {
+                    return Err(EvalError::DivisionByZero);
+                }
+                result.insert(Ordered(-exp), N::from(1).checked_div(&coef).ok_or_else(|| EvalError::Overflow(format!("1 / {}",coef)))?);
+            }
             Term::Power(Some(base),exp) => {
-                let base = Self::get_unknown_expr(base)?;
-                let exp = Self::get_unknown_expr(exp)?;
-                base * exp
+                let Term::Number(exp) = exp.as_ref() else {
+                    return Err(EvalError::Unsolvable(format!("Unsupported exponent {}",exp)));
+                };
+                let base = Self::combine(base, unknown)?;
+                if let Some((base_exp,coef)) = Self::single(&base) {
+                    result.insert(Ordered(base_exp * exp), coef.pow(exp).ok_or_else(|| EvalError::Undefined(format!("{}^{}",coef,exp)))?);
+                } else if exp.is_integer() && exp.is_positive() {
+                    result.insert(Ordered(N::from(0)), N::from(1));
+                    let times = exp.to_u32().filter(|it| *it <= EXPANSION_LIMIT).ok_or_else(|| EvalError::TooLargeToExpand(term.to_string()))?;
+                    for _ in 0..times {
+                        result = Self::product(&result, &base)?;
+                    }
+                } else {
+                    return Err(EvalError::Unsolvable(format!("Unsupported exponent {}",exp)));
+                }
+            }
+            Term::Chain(Commutative::Add,terms) => {
+                for term in terms {
+                    for (exp,coef) in Self::combine(term, unknown)? {
+                        let sum = result.entry(exp).or_insert(N::from(0));
+                        *sum = sum.checked_add(&coef).ok_or_else(|| EvalError::Overflow(format!("{} + {}",sum,coef)))?;
+                    }
+                }
             }
             Term::Chain(Commutative::Multiply,terms) => {
-                let mut result = dec!(0);
+                result.insert(Ordered(N::from(0)), N::from(1));
                 for term in terms {
-                    result += Self::get_unknown_expr(term);
+                    result = Self::product(&result, &Self::combine(term, unknown)?)?;
                 }
-                result
             }
-            Term::Chain(Commutative::Add,_) => return Err("Unexpected Add Chain!".into()),
-                _ => Num::Number(dec!(0))
-        };
-        Ok(ok)
+            Term::Function(func,b) => {
+                let Some((exp,coef)) = Self::single(&Self::combine(b, unknown)?).filter(|(exp,_)| exp.is_zero()) else {
+                    return Err(EvalError::Unsolvable(format!("Not a polynomial: {}",term)));
+                };
+                result.insert(Ordered(exp), func.apply(&coef).ok_or_else(|| EvalError::Undefined(format!("{}({})",func,coef)))?);
+            }
+            Term::Call(name,_) => return Err(EvalError::UndefinedFunction(name.clone())),
+            Term::Unit(name) => return Err(EvalError::Unsolvable(format!("Can not solve with units, found {}",name))),
+            Term::Matrix(_) | Term::MatrixProduct(_) | Term::MatrixFunction(..) => return Err(EvalError::Unsolvable("Can not solve with matrices".to_string())),
+            Term::Compare(..) | Term::Logic(..) | Term::Piecewise(..) => return Err(EvalError::Unsolvable(format!("Can not solve with conditions: {}",term))),
+            Term::Power(None,_) => return Err(EvalError::MissingBase),
+        }
+        result.retain(|_,coef| !coef.is_zero());
+        Ok(result)
     }
 
-    fn not_contains_unknown(term:&Term) -> bool {
-        match term {
-            Term::Unknown(_) => false,
-            Term::Opposite(b) => Self::not_contains_unknown(b),
-            Term::MulInverse(b) => Self::not_contains_unknown(b),
-            Term::Power(Some(base),exp) => Self::not_contains_unknown(base) && Self::not_contains_unknown(exp),
-            Term::Chain(Commutative::Add,terms) => terms.iter().all(Self::not_contains_unknown),
-            Term::Chain(Commutative::Multiply,terms) => terms.iter().all(Self::not_contains_unknown),
-            _ => true
+    fn single(poly:&BTreeMap<Ordered<N>,N>) -> Option<(N,N)> {
+        match poly.len() {
+            0 => Some((N::from(0),N::from(0))),
+            1 => poly.iter().next().map(|(exp,coef)| (exp.0.clone(),coef.clone())),
+            _ => None
         }
     }
 
-    fn combine(term:&mut Term, console: &mut ConsoleManager) -> Result<(),Box<dyn Error>> {
-        match term {
-            Term::Chain(Commutative::Add,terms) => {
-                let mut unknowns:HashMap<Num,Term> = HashMap::new();
-                for term in terms {
-                    match term {
-                        Term::Chain(Commutative::Add,terms_sub) => {
-                            terms.append(terms_sub);
-                        }
-                        _ => {
-                            let exp = Self::get_unknown_expr(term)?;
-                            let Term::Chain(_,deque) = unknowns.entry(exp).or_insert(Term::Chain(Commutative::Multiply,VecDeque::new())) else { return Err("Invalid Term!".into()) };
-
-                        }
-                    }
-                }
+    fn product(left:&BTreeMap<Ordered<N>,N>, right:&BTreeMap<Ordered<N>,N>) -> Result<BTreeMap<Ordered<N>,N>,EvalError> {
+        let overflow = |a:&N,op:&str,b:&N| EvalError::Overflow(format!("{} {} {}",a,op,b));
+        let mut result = BTreeMap::new();
+        for (exp_l,coef_l) in left {
+            for (exp_r,coef_r) in right {
+                let coef = coef_l.checked_mul(coef_r).ok_or_else(|| overflow(coef_l, "*", coef_r))?;
+                let sum = result.entry(Ordered(exp_l.0.clone() + &exp_r.0)).or_insert(N::from(0));
+                *sum = sum.checked_add(&coef).ok_or_else(|| overflow(sum, "+", &coef))?;
             }
         }
-        Ok(())
+        result.retain(|_,coef: &mut N| !coef.is_zero());
+        Ok(result)
+    }
+
+    /// Solves an equation that reduces to a polynomial in its only unknown, listing every root:
+    /// the real ones in ascending order, then the complex ones when the number type has them.
+    /// Repeated roots are listed once per multiplicity.
+    /// An equation without unknowns, such as `e + 3 = 7` with its constants, is reported as having no or infinite solutions.
+    pub fn solve(&self) -> Result<Vec<UnknownEvaluator<N>>,EvalError> {
+        if !self.equation {
+            return Err(EvalError::NotAnEquation);
+        }
+        let unknowns = self.formula.unknowns();
+        if unknowns.len() > 1 {
+            return Err(EvalError::TooManyUnknowns(unknowns.len()));
+        }
+        let unknown = unknowns.into_iter().next().unwrap_or_default();
+        let has_negative = |poly:&BTreeMap<Ordered<N>,N>| poly.keys().any(|exp| exp.0.is_negative());
+        let combined = Self::combine(&self.formula, &unknown)?;
+        let poly = Polynomial::from_map(&combined)?;
+        let mut roots = poly.roots()?;
+        if has_negative(&combined) {
+            // Clearing the denominators introduces roots at zero, which are outside the domain.
+            roots.retain(|root| !root.is_zero());
+        }
+        if roots.is_empty() {
+            return Err(if poly.degree() == 0 { EvalError::NoSolution } else { EvalError::NoRealSolution });
+        }
+        Ok(roots.into_iter().map(|root| UnknownEvaluator {
+            unknown: unknown.clone(),
+            evaluator: Evaluator{ formula: Term::Number(root), equation: false }
+        }).collect())
     }
-     */
 
     pub fn print(&mut self,console:&mut ConsoleManager) {
         console.println(self.formula.to_string())
@@ -189,41 +579,95 @@ impl Evaluator {
 
 }
 
-pub struct UnknownEvaluator {
-    pub(crate) unknown:String,
-    pub(crate) evaluator: Evaluator
+pub struct UnknownEvaluator<N> {
+    pub unknown:String,
+    pub evaluator: Evaluator<N>
 }
 
-impl UnknownEvaluator{
-    fn process(equation:Equation) -> Result<(String,Term),Box<dyn Error>>{
+impl<N:Numeric> UnknownEvaluator<N> {
+    fn process(equation:Equation<N>) -> Result<(String,Term<N>),EvalError>{
         match equation {
-            Equation(Some(Term::Unknown(name)), Some(mut right)) => {
-                Evaluator::const_expr(&mut right, None)?;
-                Ok((name,right))
+            Equation(Some(Term::Unknown(name)), Some(right)) => {
+                Ok((name,right.simplify()))
             },
-            _ => Err("Invalid Unknown Equation".into())
+            _ => Err(EvalError::InvalidBinding)
         }
     }
-    pub fn new(equation:Equation) -> Result<Self,Box<dyn Error>> {
+    pub fn new(equation:Equation<N>) -> Result<Self,EvalError> {
         let (unknown,formula) = Self::process(equation)?;
         Ok(Self {
             unknown,
-            evaluator:Evaluator{ formula}
+            evaluator:Evaluator{ formula, equation: false }
         })
     }
 
-    pub fn inline(&mut self,context:&HashMap<String,Term>) -> Result<(),Box<dyn Error>> {
+    pub fn inline(&mut self,context:&HashMap<String,Term<N>>) -> Result<(),EvalError> {
         self.evaluator.inline(context)?;
         Ok(())
     }
 
-    pub fn eval(&self, context:&HashMap<String,Term>) -> Result<Term,Box<dyn Error>> {
-        return self.evaluator.eval(context);
-    }
-
     pub fn print(&mut self,console: &mut ConsoleManager) {
         console.print(format!("{} = ",self.unknown));
         self.evaluator.print(console)
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use crate::complex::Complex;
+    use crate::testing::{evaluator, value, N};
+    use super::EvalError;
+
+    /// The roots of the equation as `x = root`, or the error solving it.
+    fn solve(equation:&str) -> Result<Vec<String>,EvalError> {
+        let roots = evaluator::<N>(equation)?.solve()?;
+        Ok(roots.iter().map(|root| format!("{} = {}",root.unknown,root.evaluator.formula)).collect())
+    }
+
+    #[test]
+    fn linear() {
+        assert_eq!(solve("2x + 4 = 0").unwrap(), ["x = -2"]);
+        assert_eq!(solve("3*(y - 1) = y + 5").unwrap(), ["y = 4"]);
+        assert_eq!(solve("x/4 = 2").unwrap(), ["x = 8"]);
+    }
+
+    #[test]
+    fn polynomial() {
+        assert_eq!(solve("x^2 - 5x + 6 = 0").unwrap(), ["x = 2", "x = 3"]);
+        assert_eq!(solve("x^3 = x").unwrap(), ["x = -1", "x = 0", "x = 1"]);
+        assert_eq!(solve("(x - 1)^2 = 0").unwrap(), ["x = 1", "x = 1"]);
+        assert_eq!(solve("x^2 + 1 = 0").unwrap(), ["x = -i", "x = i"]);
+    }
+
+    #[test]
+    fn rational() {
+        assert_eq!(solve("1/x = 2").unwrap(), ["x = 0.5"]);
+        assert!(matches!(solve("x/x = 2"), Err(EvalError::NoSolution)));
+    }
+
+    #[test]
+    fn without_solution() {
+        assert!(matches!(solve("x + 1 = x"), Err(EvalError::NoSolution)));
+        assert!(matches!(solve("x = x"), Err(EvalError::InfiniteSolutions)));
+        assert!(matches!(solve("x + y = 1"), Err(EvalError::TooManyUnknowns(2))));
+        assert!(matches!(solve("(x + 1)^100000 = 0"), Err(EvalError::TooLargeToExpand(_))));
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(solve("x + pi = 0").unwrap(), ["x = -3.141592653589793238462643383"]);
+        assert_eq!(solve("c*x = 299792458").unwrap(), ["x = 1"]);
+        assert!(matches!(solve("e + 3 = 7"), Err(EvalError::NoSolution)));
+        assert!(matches!(solve("2*pi = tau"), Err(EvalError::InfiniteSolutions)));
+    }
+
+    #[test]
+    fn error_kinds() {
+        assert!(matches!(value::<N>("x + y", &[("x",1)]), Err(EvalError::UnknownVariable(name)) if name == "y"));
+        assert!(matches!(value::<N>("1/(x - 2)", &[("x",2)]), Err(EvalError::DivisionByZero)));
+        assert!(matches!(value::<Complex<Decimal>>("x^200", &[("x",10)]), Err(EvalError::Overflow(_))));
+        assert!(matches!(evaluator::<N>("x + 1").unwrap().solve(), Err(EvalError::NotAnEquation)));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+use crate::term::{Commutative, Term};
+
+/// Highest power of a sum that is multiplied out, beyond which expanding fails rather than running on.
+pub(crate) const EXPANSION_LIMIT:u32 = 64;
+/// Most products of summands a single distribution may pair up,
+/// since powers of sums of many terms grow much faster than their degree.
+pub(crate) const EXPANSION_TERMS:usize = 2000;
+
+impl<N:Numeric> Term<N> {
+    /// Distributes products over sums and expands integer powers of sums up to `EXPANSION_LIMIT`,
+    /// so `(x+1)^2*y` becomes `x^2*y + 2*x*y + y`. Fails once a step would pair up more than `EXPANSION_TERMS` summands.
+    pub fn expand(&self) -> Result<Term<N>,EvalError> {
+        Self::expand_simplified(&self.simplify())
+    }
+
+    fn expand_simplified(term:&Term<N>) -> Result<Term<N>,EvalError> {
+        let expanded = match term {
+            Term::Chain(Commutative::Add,terms) => Self::sum(terms.iter().map(Self::expand_simplified).collect::<Result<_,_>>()?),
+            Term::Chain(Commutative::Multiply,factors) => {
+                let mut expanded = factors.iter().map(Self::expand_simplified);
+                match expanded.next().transpose()? {
+                    Some(first) => expanded.try_fold(first, |left,right| Self::distribute(left, right?, term))?,
+                    None => term.clone(),
+                }
+            }
+            Term::Power(Some(base),exp) => {
+                let base = Self::expand_simplified(base)?;
+                let exp = Self::expand_simplified(exp)?;
+                match (&base,&exp) {
+                    (Term::Chain(Commutative::Add,_),Term::Number(e)) if e.is_integer() && !e.is_zero() => {
+                        let times = e.abs().to_u32().filter(|it| *it <= EXPANSION_LIMIT)
+                            .ok_or_else(|| EvalError::TooLargeToExpand(term.to_string()))?;
+                        let mut result = base.clone();
+                        for _ in 1..times {
+                            result = Self::distribute(result, base.clone(), term)?;
+                        }
+                        if e.is_negative() { Self::power(result, Term::Number(N::from(-1))) } else { result }
+                    }
+                    _ => Self::power(base, exp),
+                }
+            }
+            Term::Function(func,arg) => Self::function(*func, Self::expand_simplified(arg)?),
+            Term::Call(name,args) => Term::Call(name.clone(), args.iter().map(Self::expand_simplified).collect::<Result<_,_>>()?),
+            Term::MatrixFunction(func,args) => Term::MatrixFunction(*func, args.iter().map(Self::expand_simplified).collect::<Result<_,_>>()?),
+            _ => term.clone(),
+        };
+        Ok(expanded)
+    }
+
+    /// Multiplies two expanded terms, pairing every summand of one with every summand of the other,
+    /// as part of expanding `origin`.
+    fn distribute(left:Term<N>, right:Term<N>, origin:&Term<N>) -> Result<Term<N>,EvalError> {
+        let summands = |term:Term<N>| match term {
+            Term::Chain(Commutative::Add,terms) => terms.into_iter().collect(),
+            _ => vec![term],
+        };
+        let right = summands(right);
+        let left = summands(left);
+        if left.len() * right.len() > EXPANSION_TERMS {
+            return Err(EvalError::TooLargeToExpand(origin.to_string()));
+        }
+        let mut result = Vec::new();
+        for l in left {
+            for r in &right {
+                result.push(Self::product(vec![l.clone(), r.clone()]));
+            }
+        }
+        Ok(Self::sum(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::EvalError;
+    use crate::testing::{formula, N};
+
+    fn expand(s:&str) -> Result<String,EvalError> {
+        Ok(formula::<N>(s).expand()?.to_string())
+    }
+
+    #[test]
+    fn distributes() {
+        assert_eq!(expand("(x + 1)^2").unwrap(), "x^2 + 2*x + 1");
+        assert_eq!(expand("(x + y)^2*(x - y)").unwrap(), "x^3 + x^2*y - x*y^2 - y^3");
+    }
+
+    #[test]
+    fn limits() {
+        assert!(expand("(x + 1)^64").is_ok());
+        assert!(matches!(expand("(x + 1)^65"), Err(EvalError::TooLargeToExpand(_))));
+        assert!(matches!(expand("(x + 1)^100000"), Err(EvalError::TooLargeToExpand(_))));
+        assert!(matches!(expand("(a + b + c + d + e + f + g + h)^8"), Err(EvalError::TooLargeToExpand(_))));
+    }
+}
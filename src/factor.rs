@@ -0,0 +1,351 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use crate::eval::{EvalError, Evaluator};
+use crate::numeric::Numeric;
+use crate::poly::Polynomial;
+use crate::term::{Commutative, Term};
+
+/// Integer polynomial, `coefs[i]` being the coefficient of `x^i`, without trailing zeros.
+type IntPoly = Vec<BigInt>;
+
+/// Upper bound on the divisor combinations tried while searching for a factor of higher degree.
+const KRONECKER_LIMIT:usize = 100_000;
+
+/// Largest magnitude whose divisors are found by trial division, about a million steps.
+const DIVISOR_LIMIT:i128 = 1_000_000_000_000;
+
+impl<N:Numeric> Term<N> {
+    /// Factors a polynomial in one unknown over the rationals.
+    /// The content is pulled out first, then each square-free part is split by its rational roots,
+    /// and what is left of degree 4 or more is searched for factors of higher degree.
+    pub fn factor(&self) -> Result<Term<N>,EvalError> {
+        let unknowns = self.unknowns();
+        if unknowns.len() > 1 {
+            return Err(EvalError::TooManyUnknowns(unknowns.len()));
+        }
+        let Some(unknown) = unknowns.into_iter().next() else { return Ok(self.simplify()) };
+        let map = Evaluator::combine(self, &unknown)?;
+        if map.keys().any(|exp| exp.0.is_negative() || !exp.0.is_integer()) {
+            return Err(EvalError::NotPolynomial(unknown));
+        }
+        let poly = Polynomial::from_map(&map)?;
+        if poly.degree() == 0 {
+            return Ok(self.simplify());
+        }
+        if !poly.coefs().iter().all(N::is_real) {
+            return Err(EvalError::ComplexCoefficients);
+        }
+
+        let fractions = poly.coefs().iter().map(N::to_fraction).collect::<Option<Vec<(i128,i128)>>>().ok_or(EvalError::TooLargeToFactor)?;
+        let denom = fractions.iter().try_fold(1i128, |acc,(_,it)| acc.checked_mul(it / acc.gcd(it))).ok_or(EvalError::TooLargeToFactor)?;
+        let ints = fractions.iter().map(|(numer,it)| BigInt::from(*numer) * (denom / it)).collect::<IntPoly>();
+        let mut content = ints.iter().fold(BigInt::zero(), |acc,it| acc.gcd(it));
+        if ints.last().is_some_and(BigInt::is_negative) {
+            content = -content;
+        }
+        let constant = content.to_i128().and_then(N::from_i128).zip(N::from_i128(denom)).map(|(content,denom)| content / denom).ok_or(EvalError::TooLargeToFactor)?;
+        let primitive = ints.iter().map(|it| it / &content).collect::<IntPoly>();
+
+        let mut factors = Vec::new();
+        for (part,multiplicity) in square_free(primitive) {
+            for factor in split(part)? {
+                factors.push((factor,multiplicity));
+            }
+        }
+        factors.sort();
+        factors.sort_by_key(|(factor,_)| factor.len());
+
+        let unknown = Term::Unknown(unknown);
+        let mut terms = factors.into_iter().map(|(factor,multiplicity)| {
+            let factor = to_term(&factor, &unknown)?;
+            Some(if multiplicity == 1 { factor } else { Term::Power(Some(factor.into()), Term::Number(N::from(multiplicity as i32)).into()) })
+        }).collect::<Option<Vec<Term<N>>>>().ok_or(EvalError::TooLargeToFactor)?;
+        if constant != N::from(1) {
+            terms.insert(0, Term::Number(constant));
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Term::Chain(Commutative::Multiply, terms.into()) })
+    }
+}
+
+fn to_term<N:Numeric>(poly:&IntPoly, unknown:&Term<N>) -> Option<Term<N>> {
+    let mut terms = poly.iter().enumerate().rev()
+        .filter(|(_,coef)| !coef.is_zero())
+        .map(|(exp,coef)| {
+            let power = Term::power(unknown.clone(), Term::Number(N::from(exp as i32)));
+            Some(Term::product(vec![Term::Number(N::from_i128(coef.to_i128()?)?), power]))
+        })
+        .collect::<Option<Vec<Term<N>>>>()?;
+    Some(if terms.len() == 1 { terms.pop().unwrap() } else { Term::Chain(Commutative::Add, terms.into()) })
+}
+
+fn trim(mut poly:IntPoly) -> IntPoly {
+    while poly.last().is_some_and(BigInt::is_zero) {
+        poly.pop();
+    }
+    poly
+}
+
+fn degree(poly:&IntPoly) -> usize {
+    poly.len().saturating_sub(1)
+}
+
+/// Divides out the content and makes the leading coefficient positive.
+fn primitive(poly:IntPoly) -> IntPoly {
+    let poly = trim(poly);
+    let mut content = poly.iter().fold(BigInt::zero(), |acc,it| acc.gcd(it));
+    if content.is_zero() {
+        return poly;
+    }
+    if poly.last().is_some_and(BigInt::is_negative) {
+        content = -content;
+    }
+    poly.into_iter().map(|it| it / &content).collect()
+}
+
+fn derivative(poly:&IntPoly) -> IntPoly {
+    trim(poly.iter().enumerate().skip(1).map(|(exp,coef)| coef * BigInt::from(exp)).collect())
+}
+
+fn sub(a:&IntPoly, b:&IntPoly) -> IntPoly {
+    let zero = BigInt::zero();
+    let len = a.len().max(b.len());
+    trim((0..len).map(|i| a.get(i).unwrap_or(&zero) - b.get(i).unwrap_or(&zero)).collect())
+}
+
+fn eval(poly:&IntPoly, x:&BigInt) -> BigInt {
+    poly.iter().rev().fold(BigInt::zero(), |acc,coef| acc * x + coef)
+}
+
+/// Quotient of `a / b` when `b` divides `a` over the integers.
+fn div_exact(a:&IntPoly, b:&IntPoly) -> Option<IntPoly> {
+    let mut rem = trim(a.clone());
+    if b.is_empty() || rem.len() < b.len() {
+        return if rem.is_empty() { Some(Vec::new()) } else { None };
+    }
+    let lead = b.last()?;
+    let mut quotient = vec![BigInt::zero(); rem.len() - b.len() + 1];
+    while !rem.is_empty() && rem.len() >= b.len() {
+        let top = rem.last()?;
+        if !top.is_multiple_of(lead) {
+            return None;
+        }
+        let shift = rem.len() - b.len();
+        quotient[shift] = top / lead;
+        for (i,coef) in b.iter().enumerate() {
+            rem[shift + i] -= &quotient[shift] * coef;
+        }
+        rem = trim(rem);
+    }
+    if rem.is_empty() { Some(quotient) } else { None }
+}
+
+/// Pseudo-remainder of `a` by `b`, scaling `a` by the leading coefficient of `b` to stay in the integers.
+fn pseudo_rem(a:&IntPoly, b:&IntPoly) -> IntPoly {
+    let lead = b.last().unwrap();
+    let mut rem = a.clone();
+    while !rem.is_empty() && rem.len() >= b.len() {
+        let top = rem.last().unwrap().clone();
+        let shift = rem.len() - b.len();
+        rem.iter_mut().for_each(|it| *it *= lead);
+        for (i,coef) in b.iter().enumerate() {
+            rem[shift + i] -= &top * coef;
+        }
+        rem = primitive(rem);
+    }
+    rem
+}
+
+fn poly_gcd(a:&IntPoly, b:&IntPoly) -> IntPoly {
+    let (mut a,mut b) = (primitive(a.clone()),primitive(b.clone()));
+    while !b.is_empty() {
+        let rem = pseudo_rem(&a, &b);
+        a = b;
+        b = rem;
+    }
+    primitive(a)
+}
+
+/// Yun's square-free decomposition of a primitive polynomial, as `(part, multiplicity)` pairs.
+fn square_free(poly:IntPoly) -> Vec<(IntPoly,u32)> {
+    let mut result = Vec::new();
+    let derived = derivative(&poly);
+    let common = poly_gcd(&poly, &derived);
+    // The quotients stay exact over the integers since every divisor is primitive (Gauss's lemma),
+    // which keeps `y` and the derivative of `w` on the same scale.
+    let mut w = div_exact(&poly, &common).unwrap_or(poly.clone());
+    let mut y = div_exact(&derived, &common).unwrap_or(derived.clone());
+    let mut z = sub(&y, &derivative(&w));
+    let mut multiplicity = 1;
+    while degree(&w) > 0 {
+        let part = poly_gcd(&w, &z);
+        if degree(&part) > 0 {
+            result.push((part.clone(),multiplicity));
+        }
+        w = div_exact(&w, &part).unwrap_or(w);
+        y = div_exact(&z, &part).unwrap_or(z);
+        z = sub(&y, &derivative(&w));
+        multiplicity += 1;
+    }
+    result
+}
+
+/// Positive divisors of `n` by trial division, refusing numbers above `DIVISOR_LIMIT`.
+fn divisors(n:&BigInt) -> Result<Vec<BigInt>,EvalError> {
+    let n = n.abs().to_i128().filter(|it| *it <= DIVISOR_LIMIT).ok_or(EvalError::TooLargeToFactor)?;
+    let mut result = Vec::new();
+    let mut i = 1;
+    while i * i <= n {
+        if n % i == 0 {
+            result.push(BigInt::from(i));
+            if i * i != n {
+                result.push(BigInt::from(n / i));
+            }
+        }
+        i += 1;
+    }
+    Ok(result)
+}
+
+/// Splits a square-free primitive polynomial into irreducible factors.
+fn split(mut poly:IntPoly) -> Result<Vec<IntPoly>,EvalError> {
+    let mut factors = Vec::new();
+    if poly.first().is_some_and(BigInt::is_zero) {
+        factors.push(vec![BigInt::zero(),BigInt::one()]);
+        poly.remove(0);
+    }
+    'roots: while degree(&poly) > 1 {
+        for p in divisors(&poly[0])? {
+            for q in divisors(poly.last().unwrap())? {
+                for p in [p.clone(),-p.clone()] {
+                    if !p.gcd(&q).is_one() {
+                        continue;
+                    }
+                    let linear = vec![-p,q.clone()];
+                    if let Some(quotient) = div_exact(&poly, &linear) {
+                        factors.push(linear);
+                        poly = quotient;
+                        continue 'roots;
+                    }
+                }
+            }
+        }
+        break;
+    }
+    let mut pending = vec![poly];
+    while let Some(poly) = pending.pop() {
+        match kronecker(&poly)? {
+            Some((a,b)) => pending.extend([a,b]),
+            None if degree(&poly) > 0 => factors.push(primitive(poly)),
+            None => {}
+        }
+    }
+    Ok(factors)
+}
+
+/// Kronecker's method: a factor of degree `d` takes, at `d + 1` points, values dividing those of `poly`,
+/// so every combination of divisors is interpolated and tried.
+fn kronecker(poly:&IntPoly) -> Result<Option<(IntPoly,IntPoly)>,EvalError> {
+    let mut tried = 0;
+    for d in 2..=degree(poly) / 2 {
+        let values = (0..=d).map(|x| eval(poly, &BigInt::from(x))).collect::<Vec<BigInt>>();
+        let choices = values.iter().map(divisors).collect::<Result<Vec<Vec<BigInt>>,EvalError>>()?;
+        let mut indices = vec![0; d + 1];
+        let mut signs = 0u32;
+        loop {
+            tried += 1;
+            if tried > KRONECKER_LIMIT {
+                return Ok(None);
+            }
+            // The first value stays positive, a factor and its negation being the same.
+            let picked = (0..=d).map(|i| {
+                let value = choices[i][indices[i]].clone();
+                if i > 0 && signs & (1 << (i - 1)) != 0 { -value } else { value }
+            }).collect::<Vec<BigInt>>();
+            if let Some(candidate) = interpolate(&picked) {
+                if degree(&candidate) == d {
+                    if let Some(quotient) = div_exact(poly, &candidate) {
+                        return Ok(Some((primitive(candidate),primitive(quotient))));
+                    }
+                }
+            }
+            signs += 1;
+            if signs < 1 << d {
+                continue;
+            }
+            signs = 0;
+            let Some(i) = (0..=d).find(|i| indices[*i] + 1 < choices[*i].len()) else { break };
+            indices[i] += 1;
+            indices[..i].iter_mut().for_each(|it| *it = 0);
+        }
+    }
+    Ok(None)
+}
+
+/// Polynomial through `(0, values[0]), (1, values[1]), …` when its coefficients are integers,
+/// built from Newton's forward differences.
+fn interpolate(values:&[BigInt]) -> Option<IntPoly> {
+    let d = values.len() - 1;
+    let mut diffs = values.to_vec();
+    let mut leading = Vec::new();
+    for _ in 0..=d {
+        leading.push(diffs[0].clone());
+        diffs = diffs.windows(2).map(|it| &it[1] - &it[0]).collect();
+    }
+    let factorial = (1..=d).map(BigInt::from).product::<BigInt>();
+    let mut numerator = vec![BigInt::zero(); d + 1];
+    let mut falling:IntPoly = vec![BigInt::one()];
+    let mut scale = factorial.clone();
+    for (k,delta) in leading.iter().enumerate() {
+        for (i,coef) in falling.iter().enumerate() {
+            numerator[i] += delta * coef * &scale;
+        }
+        // falling(x, k + 1) = falling(x, k) * (x - k), and d!/(k+1)! = d!/k! / (k+1)
+        let mut next = vec![BigInt::zero(); falling.len() + 1];
+        for (i,coef) in falling.iter().enumerate() {
+            next[i + 1] += coef;
+            next[i] -= coef * BigInt::from(k);
+        }
+        falling = next;
+        scale /= BigInt::from(k + 1);
+    }
+    if numerator.iter().any(|it| !it.is_multiple_of(&factorial)) {
+        return None;
+    }
+    Some(trim(numerator.into_iter().map(|it| it / &factorial).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::EvalError;
+    use crate::testing::{evaluator, N};
+
+    fn factor(formula:&str) -> Result<String,EvalError> {
+        let evaluator = evaluator::<N>(formula)?;
+        Ok(evaluator.formula.factor()?.to_string())
+    }
+
+    #[test]
+    fn quadratics() {
+        assert_eq!(factor("x^2 - 5x + 6").unwrap(), "(x - 3)*(x - 2)");
+        assert_eq!(factor("2x^2 - 2").unwrap(), "2*(x - 1)*(x + 1)");
+        assert_eq!(factor("4x^2 + 12x + 9").unwrap(), "(2*x + 3)^2");
+        assert_eq!(factor("x^2 + 1").unwrap(), "x^2 + 1");
+    }
+
+    #[test]
+    fn higher_degrees() {
+        assert_eq!(factor("x^3 - x").unwrap(), "(x - 1)*x*(x + 1)");
+        assert_eq!(factor("x^4 - 1").unwrap(), "(x - 1)*(x + 1)*(x^2 + 1)");
+        assert_eq!(factor("x^4 + 4").unwrap(), "(x^2 - 2*x + 2)*(x^2 + 2*x + 2)");
+        assert_eq!(factor("x^6 - 1").unwrap(), "(x - 1)*(x + 1)*(x^2 - x + 1)*(x^2 + x + 1)");
+    }
+
+    #[test]
+    fn refused() {
+        assert!(matches!(factor("x^2 - 4y^2"), Err(EvalError::TooManyUnknowns(2))));
+        assert!(matches!(factor("sin(x) + 1"), Err(EvalError::Unsolvable(_))));
+        assert!(matches!(factor("x^2 + i"), Err(EvalError::ComplexCoefficients)));
+        assert!(matches!(factor("x^2 - 1000000000000000000000000000001"), Err(EvalError::TooLargeToFactor)));
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+use crate::term::{Equation, Term};
+
+/// Nested calls a recursion may make before it is stopped, set in the REPL with `depth <calls>`.
+static DEPTH_LIMIT:AtomicUsize = AtomicUsize::new(1000);
+
+/// Stack a nested call takes at most, its frames through `inline_with` and the simplification as it returns.
+const STACK_PER_CALL:usize = 64 * 1024;
+
+pub fn depth_limit() -> usize {
+    DEPTH_LIMIT.load(Ordering::Relaxed)
+}
+
+pub fn set_depth_limit(calls:usize) {
+    DEPTH_LIMIT.store(calls, Ordering::Relaxed);
+}
+
+/// A function defined in the REPL as `f(x, y) = body`.
+pub struct UserFunction<N> {
+    params:Vec<String>,
+    body:Term<N>,
+}
+
+/// The user-defined functions, kept across formulas.
+pub struct Functions<N> {
+    table:HashMap<String,UserFunction<N>>,
+}
+
+impl<N:Numeric> Term<N> {
+    /// Replaces every unknown bound in `bindings`.
+    pub fn substitute(&self, bindings:&HashMap<String,Term<N>>) -> Term<N> {
+        let substitute = |term:&Term<N>| Box::new(term.substitute(bindings));
+        match self {
+            Term::Unknown(name) => bindings.get(name).cloned().unwrap_or(self.clone()),
+            Term::Number(_) | Term::Unit(_) => self.clone(),
+            Term::Chain(com,terms) => Term::Chain(com.clone(), terms.iter().map(|it| it.substitute(bindings)).collect()),
+            Term::Power(base,exp) => Term::Power(base.as_deref().map(substitute), substitute(exp)),
+            Term::MulInverse(term) => Term::MulInverse(substitute(term)),
+            Term::Opposite(term) => Term::Opposite(substitute(term)),
+            Term::Function(func,term) => Term::Function(*func, substitute(term)),
+            Term::Call(name,args) => Term::Call(name.clone(), args.iter().map(|it| it.substitute(bindings)).collect()),
+            Term::MatrixFunction(func,args) => Term::MatrixFunction(*func, args.iter().map(|it| it.substitute(bindings)).collect()),
+            Term::Matrix(rows) => Term::Matrix(rows.iter().map(|row| row.iter().map(|it| it.substitute(bindings)).collect()).collect()),
+            Term::MatrixProduct(factors) => Term::MatrixProduct(factors.iter().map(|it| it.substitute(bindings)).collect()),
+            Term::Compare(relation,left,right) => Term::Compare(*relation, substitute(left), substitute(right)),
+            Term::Logic(logic,terms) => Term::Logic(*logic, terms.iter().map(|it| it.substitute(bindings)).collect()),
+            Term::Piecewise(branches,otherwise) => Term::Piecewise(
+                branches.iter().map(|(cond,value)| (cond.substitute(bindings), value.substitute(bindings))).collect(),
+                otherwise.as_deref().map(substitute),
+            ),
+        }
+    }
+
+    fn collect_calls(&self, calls:&mut Vec<String>) {
+        match self {
+            Term::Call(name,args) => {
+                calls.push(name.clone());
+                args.iter().for_each(|it| it.collect_calls(calls));
+            }
+            Term::Chain(_,terms) | Term::MatrixProduct(terms) => terms.iter().for_each(|it| it.collect_calls(calls)),
+            Term::MatrixFunction(_,args) => args.iter().for_each(|it| it.collect_calls(calls)),
+            Term::Matrix(rows) => rows.iter().flatten().for_each(|it| it.collect_calls(calls)),
+            Term::Compare(_,left,right) => {
+                left.collect_calls(calls);
+                right.collect_calls(calls);
+            }
+            Term::Logic(_,terms) => terms.iter().for_each(|it| it.collect_calls(calls)),
+            Term::Piecewise(branches,otherwise) => {
+                branches.iter().flat_map(|(cond,value)| [cond,value]).for_each(|it| it.collect_calls(calls));
+                if let Some(otherwise) = otherwise {
+                    otherwise.collect_calls(calls);
+                }
+            }
+            Term::Power(base,exp) => {
+                if let Some(base) = base {
+                    base.collect_calls(calls);
+                }
+                exp.collect_calls(calls);
+            }
+            Term::MulInverse(term) | Term::Opposite(term) | Term::Function(_,term) => term.collect_calls(calls),
+            Term::Unknown(_) | Term::Number(_) | Term::Unit(_) => {}
+        }
+    }
+}
+
+impl<N:Numeric> Default for Functions<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N:Numeric> Functions<N> {
+    pub fn new() -> Self {
+        Self { table: HashMap::new() }
+    }
+
+    /// Records `equation` when it reads `f(x, ...) = body`, returning the name it was defined under.
+    /// The body may call the function itself, guarded by a condition such as `if(n <= 1, 1, n*f(n-1))`.
+    pub fn define(&mut self, equation:&Equation<N>) -> Result<Option<String>,EvalError> {
+        let Equation(Some(Term::Call(name,args)),Some(body)) = equation else { return Ok(None) };
+        let mut params = Vec::new();
+        for arg in args {
+            let Term::Unknown(param) = arg else { return Ok(None) };
+            if params.contains(param) {
+                return Err(EvalError::RepeatedParameter{function: name.clone(), param: param.clone()});
+            }
+            params.push(param.clone());
+        }
+        self.table.insert(name.clone(), UserFunction { params, body: body.clone() });
+        Ok(Some(name.clone()))
+    }
+
+    /// Whether `name` can end up calling itself, directly or through other defined functions.
+    fn is_recursive(&self, name:&str) -> bool {
+        let mut pending = vec![name.to_string()];
+        let mut seen = HashSet::new();
+        while let Some(current) = pending.pop() {
+            let Some(function) = self.table.get(&current) else { continue };
+            let mut calls = Vec::new();
+            function.body.collect_calls(&mut calls);
+            for call in calls {
+                if call == name {
+                    return true;
+                }
+                if seen.insert(call.clone()) {
+                    pending.push(call);
+                }
+            }
+        }
+        false
+    }
+
+    pub fn inline_equation(&self, equation:Equation<N>) -> Result<Equation<N>,EvalError> {
+        let Equation(left,right) = equation;
+        Ok(Equation(left.map(|it| self.inline(&it)).transpose()?, right.map(|it| self.inline(&it)).transpose()?))
+    }
+
+    /// Replaces every call with the body of the function, its parameters bound to the arguments.
+    /// A recursive function is only expanded once its arguments are known,
+    /// taking just the branch whose condition holds, so the recursion stops at its base case.
+    /// The calls are inlined on a thread whose stack is sized to the depth limit, so deep recursions stop at the limit
+    /// instead of overflowing the stack.
+    pub fn inline(&self, term:&Term<N>) -> Result<Term<N>,EvalError> {
+        let stack = STACK_PER_CALL * (depth_limit() + 16);
+        thread::scope(|scope| {
+            let inlining = thread::Builder::new().stack_size(stack).spawn_scoped(scope, || self.inline_with(term, 0))
+                .expect("the stack for the depth limit fits in memory");
+            inlining.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    /// Inlines the calls left for later, now that the unknowns bound in `context` may make their arguments known.
+    pub fn resolve(&self, term:&Term<N>, context:&HashMap<String,Term<N>>) -> Result<Term<N>,EvalError> {
+        self.inline(&term.substitute(context))
+    }
+
+    fn inline_with(&self, term:&Term<N>, depth:usize) -> Result<Term<N>,EvalError> {
+        match term {
+            Term::Call(name,args) => self.inline_call(name, args, depth),
+            Term::Piecewise(branches,otherwise) => self.inline_piecewise(branches, otherwise.as_deref(), depth),
+            Term::Unknown(_) | Term::Number(_) | Term::Unit(_) => Ok(term.clone()),
+            Term::Chain(com,terms) => {
+                let mut inlined = Vec::new();
+                for term in terms {
+                    inlined.push(self.inline_with(term, depth)?);
+                }
+                Ok(Term::Chain(com.clone(), inlined.into()))
+            }
+            Term::Matrix(_) | Term::MatrixProduct(_) | Term::MatrixFunction(..) | Term::Compare(..) | Term::Logic(..)
+            | Term::Power(..) | Term::MulInverse(_) | Term::Opposite(_) | Term::Function(..) => self.inline_nested(term, depth),
+        }
+    }
+
+    /// Inlines the less common terms, kept apart so the frame of a deep recursion through calls stays small.
+    fn inline_nested(&self, term:&Term<N>, depth:usize) -> Result<Term<N>,EvalError> {
+        let inlined = match term {
+            Term::Matrix(rows) => Term::Matrix(rows.iter()
+                .map(|row| row.iter().map(|it| self.inline_with(it, depth)).collect())
+                .collect::<Result<_,_>>()?),
+            Term::MatrixProduct(factors) => Term::MatrixProduct(factors.iter().map(|it| self.inline_with(it, depth)).collect::<Result<_,_>>()?),
+            Term::MatrixFunction(func,args) => Term::MatrixFunction(*func, args.iter().map(|it| self.inline_with(it, depth)).collect::<Result<_,_>>()?),
+            Term::Compare(relation,left,right) => Term::Compare(*relation, self.inline_with(left, depth)?.into(), self.inline_with(right, depth)?.into()),
+            Term::Logic(logic,terms) => Term::Logic(*logic, terms.iter().map(|it| self.inline_with(it, depth)).collect::<Result<_,_>>()?),
+            Term::Power(base,exp) => Term::Power(
+                base.as_deref().map(|it| self.inline_with(it, depth)).transpose()?.map(Box::new),
+                self.inline_with(exp, depth)?.into(),
+            ),
+            Term::MulInverse(term) => Term::MulInverse(self.inline_with(term, depth)?.into()),
+            Term::Opposite(term) => Term::Opposite(self.inline_with(term, depth)?.into()),
+            Term::Function(func,term) => Term::Function(*func, self.inline_with(term, depth)?.into()),
+            Term::Call(name,args) => self.inline_call(name, args, depth)?,
+            Term::Piecewise(branches,otherwise) => self.inline_piecewise(branches, otherwise.as_deref(), depth)?,
+            Term::Unknown(_) | Term::Number(_) | Term::Unit(_) => term.clone(),
+            Term::Chain(com,terms) => Term::Chain(com.clone(), terms.iter().map(|it| self.inline_with(it, depth)).collect::<Result<_,_>>()?),
+        };
+        Ok(inlined)
+    }
+
+    fn inline_call(&self, name:&str, args:&[Term<N>], depth:usize) -> Result<Term<N>,EvalError> {
+        let function = self.table.get(name).ok_or_else(|| EvalError::UndefinedFunction(name.to_string()))?;
+        if function.params.len() != args.len() {
+            return Err(EvalError::Arity{function: name.to_string(), expected: function.params.len(), found: args.len()});
+        }
+        let args = args.iter().map(|it| self.inline_with(it, depth)).collect::<Result<Vec<Term<N>>,EvalError>>()?;
+        if !self.is_recursive(name) {
+            let bindings = function.params.iter().cloned().zip(args).collect::<HashMap<String,Term<N>>>();
+            return self.inline_with(&function.body.substitute(&bindings), depth + 1);
+        }
+        // Each level is simplified as it returns, so a deep recursion folds into a number rather than a deep term.
+        let args = args.iter().map(Term::simplify).collect::<Vec<Term<N>>>();
+        if args.iter().any(|it| !it.unknowns().is_empty()) {
+            return Ok(Term::Call(name.to_string(), args));
+        }
+        if depth >= depth_limit() {
+            return Err(EvalError::RecursionLimit{function: name.to_string(), limit: depth_limit()});
+        }
+        let bindings = function.params.iter().cloned().zip(args).collect::<HashMap<String,Term<N>>>();
+        Ok(self.inline_with(&function.body.substitute(&bindings), depth + 1)?.simplify())
+    }
+
+    /// Branches are decided as they are inlined, so a value that recurses is only expanded where it applies.
+    fn inline_piecewise(&self, branches:&[(Term<N>,Term<N>)], otherwise:Option<&Term<N>>, depth:usize) -> Result<Term<N>,EvalError> {
+        let mut kept = Vec::new();
+        for (cond,value) in branches {
+            let cond = self.inline_with(cond, depth)?;
+            match cond.simplify().truth() {
+                Some(true) if kept.is_empty() => return self.inline_with(value, depth),
+                Some(true) => return Ok(Term::Piecewise(kept, Some(self.inline_with(value, depth)?.into()))),
+                Some(false) => {}
+                None => kept.push((cond, self.inline_with(value, depth)?)),
+            }
+        }
+        let otherwise = otherwise.map(|it| self.inline_with(it, depth)).transpose()?;
+        Ok(match (kept.is_empty(),otherwise) {
+            (true,Some(otherwise)) => otherwise,
+            (_,otherwise) => Term::Piecewise(kept, otherwise.map(Box::new)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::eval::{EvalError, Evaluator};
+    use crate::testing::{equation, formula, N};
+    use super::Functions;
+
+    /// Defines each of `definitions`, then evaluates `formula` with them.
+    fn eval(definitions:&[&str], s:&str) -> Result<String,EvalError> {
+        let mut functions = Functions::<N>::new();
+        for definition in definitions {
+            functions.define(&equation(definition))?;
+        }
+        let evaluator = Evaluator::new(functions.inline_equation(equation(s))?)?;
+        Ok(evaluator.eval(&HashMap::new())?.to_string())
+    }
+
+    #[test]
+    fn recursive_piecewise() {
+        assert_eq!(eval(&["fact(n) = if(n <= 1, 1, n*fact(n-1))"], "fact(5)").unwrap(), "120");
+        assert_eq!(eval(&["fib(n) = if(n < 2, n, fib(n-1) + fib(n-2))"], "fib(10)").unwrap(), "55");
+        assert_eq!(eval(&["fact(n) = if(n <= 1, 1, n*fact(n-1))"], "fact(200) / fact(199)").unwrap(), "200");
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let definitions = ["even(n) = if(n == 0, 1, odd(n-1))", "odd(n) = if(n == 0, 0, even(n-1))"];
+        assert_eq!(eval(&definitions, "even(6)").unwrap(), "1");
+        assert_eq!(eval(&definitions, "odd(6)").unwrap(), "0");
+    }
+
+    #[test]
+    fn recursion_without_base_case() {
+        assert!(matches!(eval(&["f(x) = f(x) + 1"], "f(1)"), Err(EvalError::RecursionLimit{function,..}) if function == "f"));
+    }
+
+    #[test]
+    fn undefined() {
+        assert!(matches!(eval(&[], "g(2) + 1"), Err(EvalError::UndefinedFunction(name)) if name == "g"));
+        assert!(matches!(eval(&["f(x) = x^2"], "f(1, 2)"), Err(EvalError::Arity{expected: 1, found: 2, ..})));
+    }
+
+    #[test]
+    fn deferred_until_bound() {
+        let mut functions = Functions::<N>::new();
+        functions.define(&equation("fact(n) = if(n <= 1, 1, n*fact(n-1))")).unwrap();
+        let deferred = functions.inline(&formula("fact(k) + 1")).unwrap();
+        assert_eq!(deferred.to_string(), "fact(k) + 1");
+        let context = HashMap::from([("k".to_string(), formula("4"))]);
+        assert_eq!(functions.resolve(&deferred, &context).unwrap().simplify().to_string(), "25");
+    }
+}
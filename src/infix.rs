@@ -0,0 +1,228 @@
+use std::fmt::{Display, Formatter};
+use crate::numeric::Numeric;
+use crate::term::{Commutative, Logic, Term};
+
+/// How tightly a term holds together when written out, from the loosest,
+/// in the order the parser binds its operators.
+#[derive(PartialEq,PartialOrd,Clone,Copy)]
+pub(crate) enum Precedence {
+    Or,
+    And,
+    Not,
+    Relation,
+    Sum,
+    Product,
+    MatrixProduct,
+    Sign,
+    Power,
+    Atom,
+}
+
+/// Precedence of a number by the way it is written: `3+2i` is a sum, `1/3` a quotient and `-2` negated.
+fn number_precedence(text:&str) -> Precedence {
+    let inner_sign = text.char_indices().skip(1).any(|(i,c)| (c == '+' || c == '-') && !text[..i].ends_with(['e','E']));
+    match () {
+        _ if inner_sign => Precedence::Sum,
+        _ if text.contains('/') => Precedence::Product,
+        _ if text.starts_with('-') => Precedence::Sign,
+        _ if text.len() > 1 && text.ends_with('i') => Precedence::Product,
+        _ => Precedence::Atom,
+    }
+}
+
+impl<N:Numeric> Term<N> {
+    pub(crate) fn precedence(&self) -> Precedence {
+        match self {
+            Term::Number(n) => number_precedence(&n.to_string()),
+            Term::Chain(_,terms) if terms.len() == 1 => terms[0].precedence(),
+            Term::Chain(Commutative::Add,terms) if !terms.is_empty() => Precedence::Sum,
+            Term::Chain(Commutative::Multiply,terms) if !terms.is_empty() => Precedence::Product,
+            Term::MulInverse(_) => Precedence::Product,
+            Term::MatrixProduct(_) => Precedence::MatrixProduct,
+            Term::Opposite(_) => Precedence::Sign,
+            Term::Power(..) => Precedence::Power,
+            Term::Compare(..) => Precedence::Relation,
+            Term::Logic(Logic::Or,_) => Precedence::Or,
+            Term::Logic(Logic::And,_) => Precedence::And,
+            Term::Logic(Logic::Not,_) => Precedence::Not,
+            _ => Precedence::Atom,
+        }
+    }
+
+    /// The term written out, in parentheses when it holds together less tightly than `min`.
+    pub(crate) fn grouped(&self, min:Precedence) -> String {
+        match self.precedence() < min {
+            true => format!("({})",self),
+            false => self.to_string(),
+        }
+    }
+
+    /// The term a sum subtracts instead of adding this one: `x` for `-x`, `3` for `-3` and `2*x` for `-2*x`.
+    pub(crate) fn negated(&self) -> Option<Term<N>> {
+        let negative = |n:&N| n.is_real() && n.is_negative();
+        match self {
+            Term::Opposite(term) => Some(term.as_ref().clone()),
+            Term::Number(n) if negative(n) => Some(Term::Number(-n.clone())),
+            Term::Chain(Commutative::Multiply,factors) => match factors.front() {
+                Some(Term::Opposite(term)) => {
+                    let mut factors = factors.clone();
+                    factors[0] = term.as_ref().clone();
+                    Some(Term::Chain(Commutative::Multiply, factors))
+                }
+                Some(Term::Number(n)) if negative(n) => {
+                    let mut factors = factors.clone();
+                    let coef = -n.clone();
+                    factors.pop_front();
+                    if coef != N::from(1) || factors.is_empty() {
+                        factors.push_front(Term::Number(coef));
+                    }
+                    match factors.len() {
+                        1 => factors.pop_front(),
+                        _ => Some(Term::Chain(Commutative::Multiply, factors)),
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The divisor a product divides by instead of multiplying by this factor: `y` for `1/y` and `y^2` for `y^-2`.
+    pub(crate) fn divisor(&self) -> Option<Term<N>> {
+        match self {
+            Term::MulInverse(term) => Some(term.as_ref().clone()),
+            Term::Power(Some(base),exp) => match exp.as_ref() {
+                Term::Opposite(exp) => match exp.as_ref() {
+                    Term::Number(exp) if *exp == N::from(1) => Some(base.as_ref().clone()),
+                    exp => Some(Term::Power(Some(base.clone()), exp.clone().into())),
+                },
+                Term::Number(exp) if exp.is_real() && exp.is_negative() => match -exp.clone() {
+                    exp if exp == N::from(1) => Some(base.as_ref().clone()),
+                    exp => Some(Term::Power(Some(base.clone()), Term::Number(exp).into())),
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Splits the factors of a product into those multiplied, leaving out a `1` beside others, and the divisors.
+    pub(crate) fn fraction(factors:&[Term<N>]) -> (Vec<Term<N>>,Vec<Term<N>>) {
+        let mut numerator = Vec::new();
+        let mut denominator = Vec::new();
+        for factor in factors {
+            match factor.divisor() {
+                Some(divisor) => denominator.push(divisor),
+                None => numerator.push(factor.clone()),
+            }
+        }
+        if numerator.len() > 1 {
+            numerator.retain(|it| *it != Term::Number(N::from(1)));
+        }
+        (numerator,denominator)
+    }
+}
+
+fn join<N:Numeric>(terms:&[Term<N>], min:Precedence, separator:&str) -> String {
+    terms.iter().map(|it| it.grouped(min)).collect::<Vec<String>>().join(separator)
+}
+
+/// Writes the term infix with as few parentheses as its precedence allows,
+/// subtracting what a sum adds negated and dividing by what a product multiplies inverted:
+/// `x^2 - 2*x + 1`, `x / y`.
+impl<N:Numeric> Display for Term<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f,"{}",n),
+            Self::Chain(Commutative::Add,terms) if terms.is_empty() => write!(f,"0"),
+            Self::Chain(Commutative::Multiply,terms) if terms.is_empty() => write!(f,"1"),
+            Self::Chain(_,terms) if terms.len() == 1 => write!(f,"{}",terms[0]),
+            Self::Chain(Commutative::Add,terms) => {
+                write!(f,"{}",terms[0].grouped(Precedence::Sum))?;
+                for term in terms.iter().skip(1) {
+                    match term.negated() {
+                        Some(negated) => write!(f," - {}",negated.grouped(Precedence::Product))?,
+                        None => write!(f," + {}",term.grouped(Precedence::Product))?,
+                    }
+                }
+                Ok(())
+            }
+            Self::Chain(Commutative::Multiply,factors) => {
+                let (numerator,denominator) = Self::fraction(&factors.iter().cloned().collect::<Vec<Term<N>>>());
+                match numerator.as_slice() {
+                    [] => write!(f,"1")?,
+                    [Term::Number(n),rest @ ..] if !rest.is_empty() && *n == N::from(-1) => write!(f,"-{}",join(rest, Precedence::Product, "*"))?,
+                    _ => write!(f,"{}",join(&numerator, Precedence::Product, "*"))?,
+                }
+                for divisor in denominator {
+                    write!(f," / {}",divisor.grouped(Precedence::MatrixProduct))?;
+                }
+                Ok(())
+            }
+            Self::MulInverse(term) => write!(f,"1 / {}",term.grouped(Precedence::MatrixProduct)),
+            Self::Opposite(term) => write!(f,"-{}",term.grouped(Precedence::Sign)),
+            Self::Power(base,exp) => {
+                let base = base.as_ref().map(|it| it.grouped(Precedence::Atom)).unwrap_or("none".to_string());
+                write!(f,"{}^{}",base,exp.grouped(Precedence::Sign))
+            }
+            Self::MatrixProduct(factors) => {
+                let factors = factors.iter().enumerate()
+                    .map(|(i,it)| it.grouped(if i == 0 { Precedence::MatrixProduct } else { Precedence::Sign }))
+                    .collect::<Vec<String>>();
+                write!(f,"{}",factors.join(" @ "))
+            }
+            Self::Function(func,term) => write!(f,"{}({})",func,term),
+            Self::MatrixFunction(func,args) => write!(f,"{}({})",func,join(args, Precedence::Or, ", ")),
+            Self::Call(name,args) => write!(f,"{}({})",name,join(args, Precedence::Or, ", ")),
+            Self::Unknown(name) => write!(f,"{}",name),
+            Self::Unit(name) => write!(f,"[{}]",name),
+            Self::Matrix(rows) => write!(f,"[{}]",rows.iter().map(|row| join(row, Precedence::Or, ", ")).collect::<Vec<String>>().join("; ")),
+            Self::Compare(relation,left,right) => write!(f,"{} {} {}",left.grouped(Precedence::Sum),relation,right.grouped(Precedence::Sum)),
+            Self::Logic(Logic::Not,terms) => write!(f,"not {}",join(terms, Precedence::Relation, " ")),
+            Self::Logic(logic,terms) => {
+                let min = if *logic == Logic::Or { Precedence::And } else { Precedence::Not };
+                write!(f,"{}",join(terms, min, &format!(" {} ",logic)))
+            }
+            Self::Piecewise(branches,otherwise) => {
+                let mut args = branches.iter().flat_map(|(cond,value)| [cond.to_string(), value.to_string()]).collect::<Vec<String>>();
+                args.extend(otherwise.iter().map(|it| it.to_string()));
+                write!(f,"piecewise({})",args.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{formula, N};
+
+    /// The formula printed back as parsed.
+    fn infix(s:&str) -> String {
+        formula::<N>(s).to_string()
+    }
+
+    #[test]
+    fn round_trip() {
+        let formulas = ["x^2 - 2*x + 1", "x / y", "(a + b)*c", "a - (b + c)", "a - (b - c)", "-x^2", "(-x)^2", "2^3^2", "(2^3)^2",
+            "x^-2", "a / (b*c)", "2*-3", "-(a + b)", "sin(x)^2", "f(x, y + 1)", "x < 1 and y > 2 or z == 3", "[1, 2; 3, x]", "A @ B @ C"];
+        for formula in formulas {
+            assert_eq!(infix(formula), formula);
+        }
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        assert_eq!(infix("((a + b)) + (c)"), "a + b + c");
+        assert_eq!(infix("(a*b)*c"), "a*b*c");
+        assert_eq!(infix("a*(b / c)"), "a*b / c");
+        assert_eq!(infix("(x^2)"), "x^2");
+    }
+
+    #[test]
+    fn units() {
+        for formula in ["2*[h]", "9.81*[m] / [s]^2", "[km]^2 + x*[m]"] {
+            assert_eq!(infix(formula), formula);
+        }
+        assert_eq!(infix("3 [kg*m/s^2]"), "3*[kg]*[m] / [s]^2");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+use crate::term::{Commutative, Function, Term};
+
+impl<N:Numeric> Term<N> {
+    /// Antiderivative with respect to `unknown`, simplified and without the constant of integration.
+    /// Covers polynomials, `1/x`, exponentials, `sin` and `cos`, linear substitutions of these and their sums;
+    /// anything else is reported as not integrable.
+    pub fn integrate(&self, unknown:&str) -> Result<Term<N>,EvalError> {
+        let simplified = self.simplify();
+        let integral = match Self::antiderive(&simplified, unknown) {
+            Some(integral) => integral,
+            None => Self::antiderive(&simplified.expand()?, unknown)
+                .ok_or_else(|| EvalError::NotIntegrable{term: self.to_string(), unknown: unknown.to_string()})?,
+        };
+        Ok(integral.simplify())
+    }
+
+    /// The slope of `term` when it is linear in `unknown`.
+    fn slope(term:&Term<N>, unknown:&str) -> Option<Term<N>> {
+        let slope = term.diff(unknown).ok()?;
+        match slope {
+            Term::Number(n) if n.is_zero() => None,
+            _ if slope.unknowns().contains(unknown) => None,
+            _ => Some(slope),
+        }
+    }
+
+    fn antiderive(term:&Term<N>, unknown:&str) -> Option<Term<N>> {
+        if !term.unknowns().contains(unknown) {
+            return Some(Term::product(vec![term.clone(), Term::Unknown(unknown.to_string())]));
+        }
+        match term {
+            Term::Unknown(_) => Some(Term::product(vec![Term::Number(N::from(1) / N::from(2)), Term::power(term.clone(), Term::Number(N::from(2)))])),
+            Term::Chain(Commutative::Add,terms) => {
+                let mut integrals = Vec::new();
+                for term in terms {
+                    integrals.push(Self::antiderive(term, unknown)?);
+                }
+                Some(Term::Chain(Commutative::Add, integrals.into()))
+            }
+            // Constant factors are pulled out, leaving a single factor that depends on the unknown.
+            Term::Chain(Commutative::Multiply,factors) => {
+                let (dependent,constant):(Vec<Term<N>>,Vec<Term<N>>) = factors.iter().cloned().partition(|it| it.unknowns().contains(unknown));
+                let [dependent] = dependent.as_slice() else { return None };
+                let mut product = constant;
+                product.push(Self::antiderive(dependent, unknown)?);
+                Some(Term::product(product))
+            }
+            Term::Power(Some(base),exp) => {
+                match (base.unknowns().contains(unknown),exp.unknowns().contains(unknown)) {
+                    // ∫ u^n = u^(n+1) / ((n+1) * u'),  ∫ u^-1 = ln(u) / u'
+                    (true,false) => {
+                        let slope = Self::slope(base, unknown)?;
+                        if **exp == Term::Number(N::from(-1)) {
+                            return Some(Term::product(vec![Term::Function(Function::Ln, base.clone()), Term::MulInverse(slope.into())]));
+                        }
+                        let raised = Term::Chain(Commutative::Add, [*exp.clone(),Term::Number(N::from(1))].into());
+                        Some(Term::product(vec![
+                            Term::Power(Some(base.clone()), raised.clone().into()),
+                            Term::MulInverse(Term::product(vec![raised, slope]).into()),
+                        ]))
+                    }
+                    // ∫ c^u = c^u / (ln(c) * u')
+                    (false,true) => {
+                        let slope = Self::slope(exp, unknown)?;
+                        Some(Term::product(vec![
+                            term.clone(),
+                            Term::MulInverse(Term::product(vec![Term::Function(Function::Ln, base.clone()), slope]).into()),
+                        ]))
+                    }
+                    _ => None,
+                }
+            }
+            // ∫ f(u) = F(u) / u' for linear u
+            Term::Function(func,u) => {
+                let slope = Self::slope(u, unknown)?;
+                let integral = match func {
+                    Function::Exp => term.clone(),
+                    Function::Sin => Term::Opposite(Term::Function(Function::Cos, u.clone()).into()),
+                    Function::Cos => Term::Function(Function::Sin, u.clone()),
+                    _ => return None,
+                };
+                Some(Term::product(vec![integral, Term::MulInverse(slope.into())]))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::EvalError;
+    use crate::testing::{evaluator, formula, N};
+
+    fn integrate(s:&str) -> Result<String,EvalError> {
+        Ok(formula::<N>(s).integrate("x")?.to_string())
+    }
+
+    fn between(s:&str, low:&str, high:&str) -> Result<String,EvalError> {
+        Ok(evaluator::<N>(s)?.integrate_between("x", formula(low), formula(high), &Default::default())?.to_string())
+    }
+
+    #[test]
+    fn polynomials() {
+        assert_eq!(integrate("4x^3 + 2x").unwrap(), "x^4 + x^2");
+        assert_eq!(integrate("(2x + 1)^3").unwrap(), "0.125*(2*x + 1)^4");
+        assert_eq!(integrate("1/x").unwrap(), "ln(x)");
+        assert_eq!(integrate("y").unwrap(), "x*y");
+    }
+
+    #[test]
+    fn elementary() {
+        assert_eq!(integrate("e^(2x)").unwrap(), "0.5*e^(2*x)");
+        assert_eq!(integrate("exp(x)").unwrap(), "exp(x)");
+        assert_eq!(integrate("sin(x)").unwrap(), "-cos(x)");
+        assert_eq!(integrate("cos(2x)").unwrap(), "0.5*sin(2*x)");
+        assert!(matches!(integrate("sin(x^2)"), Err(EvalError::NotIntegrable{..})));
+    }
+
+    #[test]
+    fn definite() {
+        assert_eq!(between("x^2", "0", "3").unwrap(), "9");
+        assert_eq!(between("sin(x)", "0", "pi").unwrap(), "2");
+        assert_eq!(between("1/x", "1", "e").unwrap(), "1");
+    }
+}
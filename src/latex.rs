@@ -0,0 +1,213 @@
+use crate::infix::Precedence;
+use crate::numeric::Numeric;
+use crate::term::{Commutative, Equation, Function, Logic, MatrixFunction, Relation, Term};
+
+/// A number as LaTeX: `1/3` as a fraction and `1.5e-9` times a power of ten.
+fn number_latex(text:&str) -> String {
+    let (sign,digits) = match text.strip_prefix('-') {
+        Some(digits) => ("-",digits),
+        None => ("",text),
+    };
+    let plain = |it:&str| it.chars().all(|c| c.is_ascii_digit() || c == '.');
+    match (digits.split_once('/'),digits.split_once(['e','E'])) {
+        (Some((p,q)),_) if plain(p) && plain(q) => format!("{}\\frac{{{}}}{{{}}}",sign,p,q),
+        (_,Some((mantissa,exp))) if plain(mantissa) && exp.trim_start_matches(['-','+']).chars().all(|c| c.is_ascii_digit()) =>
+            format!("{}{} \\times 10^{{{}}}",sign,mantissa,exp.trim_start_matches('+')),
+        _ => text.to_string(),
+    }
+}
+
+/// A name as LaTeX: Greek letters by their command, other names longer than a letter upright.
+fn name_latex(name:&str) -> String {
+    const GREEK:[&str;24] = ["alpha","beta","gamma","delta","epsilon","zeta","eta","theta","iota","kappa","lambda","mu",
+        "nu","xi","pi","rho","sigma","tau","upsilon","phi","chi","psi","omega","hbar"];
+    match name {
+        _ if GREEK.contains(&name) => format!("\\{}",name),
+        _ if name.chars().count() > 1 => format!("\\mathrm{{{}}}",name),
+        _ => name.to_string(),
+    }
+}
+
+fn relation_latex(relation:Relation) -> &'static str {
+    match relation {
+        Relation::Less => "<",
+        Relation::LessEqual => "\\le",
+        Relation::Greater => ">",
+        Relation::GreaterEqual => "\\ge",
+        Relation::Equal => "=",
+        Relation::NotEqual => "\\ne",
+    }
+}
+
+fn join<N:Numeric>(terms:&[Term<N>], min:Precedence, separator:&str) -> String {
+    terms.iter().map(|it| it.latex_grouped(min)).collect::<Vec<String>>().join(separator)
+}
+
+/// Factors joined with `\cdot`, those after the first in parentheses when negative: `2 \cdot \left(-3\right)`.
+fn product_latex<N:Numeric>(factors:&[Term<N>]) -> String {
+    factors.iter().enumerate()
+        .map(|(i,it)| if i == 0 { it.latex_grouped(Precedence::Product) } else { it.latex_operand(Precedence::Product) })
+        .collect::<Vec<String>>().join(" \\cdot ")
+}
+
+/// The factors of a product joined with `\cdot`, or a lone factor grouped below `min`.
+fn factors_latex<N:Numeric>(term:&Term<N>, min:Precedence) -> String {
+    match term {
+        Term::Chain(Commutative::Multiply,factors) if factors.len() > 1 => product_latex(&factors.iter().cloned().collect::<Vec<Term<N>>>()),
+        term => term.latex_grouped(min),
+    }
+}
+
+impl<N:Numeric> Term<N> {
+    /// The term as LaTeX, in `\left( \right)` when it holds together less tightly than `min`.
+    fn latex_grouped(&self, min:Precedence) -> String {
+        match self.precedence() < min {
+            true => format!("\\left({}\\right)",self.to_latex()),
+            false => self.to_latex(),
+        }
+    }
+
+    /// The term as LaTeX right after an operator, also in parentheses when it starts with a minus sign.
+    fn latex_operand(&self, min:Precedence) -> String {
+        let latex = self.latex_grouped(min);
+        match latex.starts_with('-') {
+            true => format!("\\left({}\\right)",latex),
+            false => latex,
+        }
+    }
+
+    /// Writes the term as LaTeX with the parentheses of its infix form,
+    /// dividing as `\frac{}{}`, raising to superscripts and multiplying with `\cdot`:
+    /// `x^{2} - 2 \cdot x + 1`, `\frac{x}{y}`.
+    pub fn to_latex(&self) -> String {
+        match self {
+            Self::Number(n) => number_latex(&n.to_string()),
+            Self::Chain(Commutative::Add,terms) if terms.is_empty() => "0".to_string(),
+            Self::Chain(Commutative::Multiply,terms) if terms.is_empty() => "1".to_string(),
+            Self::Chain(_,terms) if terms.len() == 1 => terms[0].to_latex(),
+            Self::Chain(Commutative::Add,terms) => {
+                let mut latex = terms[0].latex_grouped(Precedence::Sum);
+                for term in terms.iter().skip(1) {
+                    match term.negated() {
+                        Some(negated) => latex += &format!(" - {}",negated.latex_operand(Precedence::Product)),
+                        None => latex += &format!(" + {}",term.latex_operand(Precedence::Product)),
+                    }
+                }
+                latex
+            }
+            Self::Chain(Commutative::Multiply,factors) => {
+                let (numerator,denominator) = Self::fraction(&factors.iter().cloned().collect::<Vec<Term<N>>>());
+                let numerator = match numerator.len() {
+                    0 => Term::Number(N::from(1)),
+                    1 => numerator[0].clone(),
+                    _ => Term::Chain(Commutative::Multiply, numerator.into()),
+                };
+                let (sign,numerator) = match numerator.negated() {
+                    Some(negated) => ("-",negated),
+                    None => ("",numerator),
+                };
+                match denominator.len() {
+                    0 => format!("{}{}",sign,factors_latex(&numerator, if sign.is_empty() { Precedence::Or } else { Precedence::Sign })),
+                    1 => format!("{}\\frac{{{}}}{{{}}}",sign,factors_latex(&numerator, Precedence::Or),denominator[0].to_latex()),
+                    _ => format!("{}\\frac{{{}}}{{{}}}",sign,factors_latex(&numerator, Precedence::Or),product_latex(&denominator)),
+                }
+            }
+            Self::MulInverse(term) => format!("\\frac{{1}}{{{}}}",term.to_latex()),
+            Self::Opposite(term) => format!("-{}",term.latex_grouped(Precedence::Sign)),
+            Self::Power(base,exp) => {
+                let base = base.as_ref().map(|it| it.latex_grouped(Precedence::Atom)).unwrap_or("none".to_string());
+                format!("{}^{{{}}}",base,exp.to_latex())
+            }
+            Self::MatrixProduct(factors) => {
+                factors.iter().enumerate()
+                    .map(|(i,it)| it.latex_grouped(if i == 0 { Precedence::MatrixProduct } else { Precedence::Sign }))
+                    .collect::<Vec<String>>().join(" ")
+            }
+            Self::Function(Function::Sqrt,term) => format!("\\sqrt{{{}}}",term.to_latex()),
+            Self::Function(Function::Abs,term) => format!("\\left|{}\\right|",term.to_latex()),
+            Self::Function(func,term) => format!("\\{}\\left({}\\right)",func,term.to_latex()),
+            Self::MatrixFunction(MatrixFunction::Transpose,args) => format!("{}^{{T}}",args[0].latex_grouped(Precedence::Atom)),
+            Self::MatrixFunction(MatrixFunction::Inverse,args) => format!("{}^{{-1}}",args[0].latex_grouped(Precedence::Atom)),
+            Self::MatrixFunction(MatrixFunction::Det,args) => format!("\\det\\left({}\\right)",args[0].to_latex()),
+            Self::MatrixFunction(MatrixFunction::Solve,args) => format!("\\mathrm{{solve}}\\left({}\\right)",join(args, Precedence::Or, ", ")),
+            Self::Call(name,args) => format!("{}\\left({}\\right)",name_latex(name),join(args, Precedence::Or, ", ")),
+            Self::Unknown(name) => name_latex(name),
+            Self::Unit(name) => format!("\\mathrm{{{}}}",name),
+            Self::Matrix(rows) => {
+                let rows = rows.iter().map(|row| join(row, Precedence::Or, " & ")).collect::<Vec<String>>();
+                format!("\\begin{{pmatrix}} {} \\end{{pmatrix}}",rows.join(" \\\\ "))
+            }
+            Self::Compare(relation,left,right) =>
+                format!("{} {} {}",left.latex_grouped(Precedence::Sum),relation_latex(*relation),right.latex_grouped(Precedence::Sum)),
+            Self::Logic(Logic::Not,terms) => format!("\\lnot {}",join(terms, Precedence::Relation, " ")),
+            Self::Logic(Logic::And,terms) => join(terms, Precedence::Not, " \\land "),
+            Self::Logic(Logic::Or,terms) => join(terms, Precedence::And, " \\lor "),
+            Self::Piecewise(branches,otherwise) => {
+                let mut cases = branches.iter()
+                    .map(|(cond,value)| format!("{} & \\text{{if }} {}",value.to_latex(),cond.to_latex()))
+                    .collect::<Vec<String>>();
+                cases.extend(otherwise.iter().map(|it| format!("{} & \\text{{otherwise}}",it.to_latex())));
+                format!("\\begin{{cases}} {} \\end{{cases}}",cases.join(" \\\\ "))
+            }
+        }
+    }
+}
+
+impl<N:Numeric> Equation<N> {
+    /// Writes both sides as LaTeX around `=`, or just the one side of a plain formula.
+    pub fn to_latex(&self) -> String {
+        match self {
+            Equation(Some(left),Some(right)) => format!("{} = {}",left.to_latex(),right.to_latex()),
+            Equation(Some(side),None) | Equation(None,Some(side)) => side.to_latex(),
+            Equation(None,None) => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{equation, N};
+
+    fn latex(s:&str) -> String {
+        equation::<N>(s).to_latex()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(latex("x^2 - 2*x + 1"), "x^{2} - 2 \\cdot x + 1");
+        assert_eq!(latex("(a + b)*c"), "\\left(a + b\\right) \\cdot c");
+        assert_eq!(latex("2^3^2"), "2^{3^{2}}");
+        assert_eq!(latex("(-x)^2"), "\\left(-x\\right)^{2}");
+        assert_eq!(latex("x^-2"), "x^{-2}");
+    }
+
+    #[test]
+    fn negative_operands() {
+        assert_eq!(latex("2*-3"), "2 \\cdot \\left(-3\\right)");
+        assert_eq!(latex("a - -b"), "a - \\left(-b\\right)");
+        assert_eq!(latex("x*-y*-2"), "x \\cdot \\left(-y\\right) \\cdot \\left(-2\\right)");
+        assert_eq!(latex("a / (2*-x)"), "\\frac{a}{2 \\cdot \\left(-x\\right)}");
+        assert_eq!(latex("-2*x"), "-2 \\cdot x");
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(latex("x / y"), "\\frac{x}{y}");
+        assert_eq!(latex("a / (b*c)"), "\\frac{a}{b \\cdot c}");
+        assert_eq!(latex("1/3"), "\\frac{1}{3}");
+        assert_eq!(latex("-(a + b) / 2"), "-\\frac{a + b}{2}");
+    }
+
+    #[test]
+    fn names_and_functions() {
+        assert_eq!(latex("sin(theta)^2 + sqrt(x)"), "\\sin\\left(\\theta\\right)^{2} + \\sqrt{x}");
+        assert_eq!(latex("2 [h] + speed"), "2 \\cdot \\mathrm{h} + \\mathrm{speed}");
+        assert_eq!(latex("[1, 2; 3, x]"), "\\begin{pmatrix} 1 & 2 \\\\ 3 & x \\end{pmatrix}");
+    }
+
+    #[test]
+    fn equations() {
+        assert_eq!(latex("x^2 = 2"), "x^{2} = 2");
+        assert_eq!(latex("if(x < 0, -x, x)"), "\\begin{cases} -x & \\text{if } x < 0 \\\\ x & \\text{otherwise} \\end{cases}");
+    }
+}
@@ -0,0 +1,27 @@
+//! Evaluates, solves and prints formulas; the REPL in `main.rs` is built on it.
+//! A formula read by `parser::parse` is written as LaTeX by `Equation::to_latex` or `Term::to_latex`.
+
+pub mod complex;
+pub mod condition;
+pub mod console;
+pub mod constant;
+pub mod diff;
+pub mod elementary;
+pub mod eval;
+pub mod expand;
+pub mod factor;
+pub mod function;
+pub mod infix;
+pub mod integrate;
+pub mod latex;
+pub mod matrix;
+pub mod num;
+pub mod numeric;
+pub mod term;
+pub mod parser;
+pub mod poly;
+pub mod simplify;
+pub mod system;
+#[cfg(test)]
+mod testing;
+pub mod unit;
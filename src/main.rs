@@ -1,37 +1,97 @@
-mod console;
-mod eval;
-mod term;
-mod parser;
-
 use std::collections::HashMap;
 use std::error::Error;
-use crate::console::ConsoleManager;
-use crate::eval::{Evaluator, UnknownEvaluator};
+use calculator::{function, num, parser, unit};
+use calculator::complex::Complex;
+use calculator::console::{ConsoleError, ConsoleManager};
+use calculator::constant::Constant;
+use calculator::eval::{EvalError, Evaluator, UnknownEvaluator};
+use calculator::function::Functions;
+use calculator::num::{Notation, Num};
+use calculator::numeric::Numeric;
+use calculator::parser::ParseError;
+use rust_decimal::Decimal;
+use calculator::system::LinearSystem;
+use calculator::term::{Equation, Term};
 
+/// Runs on complex numbers with exact rational parts,
+/// or on `--decimal` 28 digit decimals or `--float` hardware floats.
 fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("--decimal") => run::<Complex<Decimal>>(),
+        Some("--float") => run::<Complex<f64>>(),
+        _ => run::<Complex<Num>>(),
+    }
+}
+
+fn run<N:Numeric>() {
     let mut console = ConsoleManager::new();
+    let mut functions = Functions::<N>::new();
     console.println("Welcome to Glom's Calculator!");
-    while let Err(err) = start(&mut console) {
-        console.println(&format!("Error: {}", err));
+    while let Err(err) = start(&mut console, &mut functions) {
+        if let Some(ConsoleError::Closed) = err.downcast_ref() {
+            break;
+        }
+        console.println(format!("Error: {}", err));
     }
 }
 
-fn start(console: &mut ConsoleManager) -> Result<(),Box<dyn Error>>{
+fn start<N:Numeric>(console: &mut ConsoleManager, functions: &mut Functions<N>) -> Result<(),Box<dyn Error>>{
     loop {
         let input = console.input()?;
         if input == "exit" {
             break;
         }
+        if apply_setting(console, &input)? {
+            continue
+        }
+        if input == "units" {
+            unit::UNITS.iter().for_each(|unit| console.println(format!("{} - {}",unit.name,unit.description)));
+            continue
+        }
+        if let Some(formula) = input.strip_prefix("latex ") {
+            let eq:Equation<N> = parser::parse(formula).map_err(|err| point_at(console, formula, err, true))?;
+            console.println(eq.to_latex());
+            continue
+        }
         console.println("Formula:");
         console.println(&input);
-        let eq = parser::parse(&input)?;
+        if parser::statements(&input).len() > 1 {
+            solve_system(console, functions, &input)?;
+            continue
+        }
+        if let Some((quantity,units)) = input.split_once(" to ") {
+            let quantity = functions.inline_equation(parser::parse(quantity).map_err(|err| point_at(console, quantity, err, false))?)?;
+            console.println(quantity.to_string());
+            console.println("Result:");
+            convert(console, &Evaluator::new(quantity)?.eval(&HashMap::new())?, units)?;
+            continue
+        }
+        let eq = parser::parse(&input).map_err(|err| point_at(console, &input, err, false))?;
         console.println(eq.to_string());
+        if let Some(name) = functions.define(&eq)? {
+            if let Equation(Some(Term::Call(_,params)),_) = &eq {
+                params.iter().filter_map(|it| match it { Term::Unknown(name) => Some(name), _ => None }).for_each(|it| warn_shadowing(console, it));
+            }
+            console.println(format!("Defined {}",name));
+            continue
+        }
+        let eq = functions.inline_equation(eq)?;
         console.println("Compile...");
         let mut evaluator = Evaluator::new(eq)?;
         evaluator.print(console);
+        if evaluator.equation && evaluator.formula.unknowns().len() <= 1 {
+            console.println("Solution:");
+            evaluator.solve()?.iter_mut().for_each(|root| root.print(console));
+            continue
+        }
         console.println("As you will:");
         let mut context = HashMap::new();
         while let Ok(input) = console.input() {
+            if apply_setting(console, &input)? {
+                evaluator.print(console);
+                console.println("As you will:");
+                continue
+            }
             match input.as_str() {
                 "inline" => {
                     evaluator.inline(&context)?;
@@ -40,25 +100,168 @@ fn start(console: &mut ConsoleManager) -> Result<(),Box<dyn Error>>{
                     console.println("As you will:");
                     continue
                 }
+                "expand" => {
+                    evaluator.expand()?;
+                    evaluator.print(console);
+                    console.println("As you will:");
+                    continue
+                }
+                "factor" => {
+                    evaluator.factor()?;
+                    evaluator.print(console);
+                    console.println("As you will:");
+                    continue
+                }
+                "latex" => {
+                    match evaluator.equation {
+                        true => console.println(Equation(Some(evaluator.formula.clone()), Some(Term::Number(N::from(0)))).to_latex()),
+                        false => console.println(evaluator.formula.to_latex()),
+                    }
+                    console.println("As you will:");
+                    continue
+                }
                 "" => break,
                 _ => {}
             }
-            let unknown_eq = parser::parse(&input)?;
+            if let Some(args) = input.strip_prefix("integrate ") {
+                match args.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                    [unknown] => {
+                        warn_shadowing(console, unknown);
+                        evaluator.integrate(unknown)?;
+                        evaluator.print(console);
+                    }
+                    [unknown,low,high] => {
+                        warn_shadowing(console, unknown);
+                        let low = parser::parse(low).map_err(|err| point_at(console, low, err, true))?;
+                        let high = parser::parse(high).map_err(|err| point_at(console, high, err, true))?;
+                        let low = functions.inline(&low.1.ok_or("Missing lower bound!")?)?;
+                        let high = functions.inline(&high.1.ok_or("Missing upper bound!")?)?;
+                        console.println("Integral:");
+                        console.println(evaluator.integrate_between(unknown, low, high, &context)?.to_string());
+                    }
+                    _ => console.println("Usage: integrate <unknown> [<low> <high>]"),
+                }
+                console.println("As you will:");
+                continue
+            }
+            if let Some(units) = input.strip_prefix("to ") {
+                console.println("Result:");
+                let value = evaluate(console, functions, &evaluator, &mut context)?;
+                convert(console, &value, units)?;
+                console.println("As you will:");
+                continue
+            }
+            if let Some(unknown) = input.strip_prefix("diff ") {
+                warn_shadowing(console, unknown.trim());
+                evaluator.diff(unknown.trim())?;
+                evaluator.print(console);
+                console.println("As you will:");
+                continue
+            }
+            let unknown_eq = functions.inline_equation(parser::parse(&input).map_err(|err| point_at(console, &input, err, true))?)?;
             match UnknownEvaluator::new(unknown_eq) {
                 Ok(mut unknown_eq) => {
                     let origin = unknown_eq.evaluator.formula.to_string();
                     unknown_eq.inline(&context)?;
                     let current = unknown_eq.evaluator.formula.to_string();
                     console.println(format!("{} = {} := {}",unknown_eq.unknown,origin,current));
+                    warn_shadowing(console, &unknown_eq.unknown);
                     context.insert(unknown_eq.unknown, unknown_eq.evaluator.formula);
                 }
                 Err(err) => {
-                    console.println(&format!("Error: {}", err));
+                    console.println(format!("Error: {}", err));
                 }
             }
         }
         console.println("Result:");
-        console.println(evaluator.eval(&context)?.to_string());
+        if evaluator.equation {
+            evaluator.inline(&context)?;
+            evaluator.solve()?.iter_mut().for_each(|root| root.print(console));
+        } else {
+            let value = evaluate(console, functions, &evaluator, &mut context)?;
+            console.println(value.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Warns that `name`, used as an unknown, hides the constant of the same name.
+fn warn_shadowing(console: &mut ConsoleManager, name: &str) {
+    if let Some(constant) = Constant::find(name) {
+        console.println(format!("Warning: {} shadows the constant {} ({})",name,constant.name,constant.description));
+    }
+}
+
+/// Evaluates within `context`, asking for the value of each variable still missing.
+fn evaluate<N:Numeric>(console: &mut ConsoleManager, functions: &Functions<N>, evaluator: &Evaluator<N>, context: &mut HashMap<String,Term<N>>) -> Result<Term<N>,Box<dyn Error>>{
+    loop {
+        let resolved = Evaluator { formula: functions.resolve(&evaluator.formula, context)?, equation: evaluator.equation };
+        match resolved.eval(context) {
+            Err(EvalError::UnknownVariable(name)) => {
+                console.println(format!("Value of {}:",name));
+                let input = console.input()?;
+                let value = parser::parse(&input).map_err(|err| point_at(console, &input, err, true))?;
+                let mut unknown_eq = UnknownEvaluator::new(functions.inline_equation(Equation(Some(Term::Unknown(name)), value.1))?)?;
+                unknown_eq.inline(context)?;
+                context.insert(unknown_eq.unknown, unknown_eq.evaluator.formula);
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// Prints the quantity `value` in `units`, such as `10 [m/s]` as `36 km/h`.
+fn convert<N:Numeric>(console: &mut ConsoleManager, value: &Term<N>, units: &str) -> Result<(),Box<dyn Error>>{
+    let units = units.trim();
+    let parsed = parser::parse_units(units).map_err(|err| point_at(console, units, err, true))?;
+    console.println(format!("{} {}",unit::convert(value, &parsed)?,units));
+    Ok(())
+}
+
+/// Puts a `^` under the part of `line` the parse error is about, first printing the line unless it is
+/// the one just printed.
+fn point_at(console: &mut ConsoleManager, line: &str, err: ParseError, echo: bool) -> Box<dyn Error>{
+    if echo {
+        console.println(line);
+    }
+    console.println(err.pointer(line));
+    err.into()
+}
+
+/// Applies `rational`, `decimal`, `precision <digits>` or `depth <calls>`, telling whether the input was a setting.
+fn apply_setting(console: &mut ConsoleManager, input: &str) -> Result<bool,Box<dyn Error>>{
+    if let Some(notation) = Notation::from_name(input) {
+        notation.set();
+        console.println(format!("Numbers are shown as {}",notation));
+        return Ok(true);
+    }
+    match input.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        ["precision"] => console.println(format!("Working precision is {} digits",num::precision())),
+        ["precision",digits] => {
+            let digits = digits.parse::<u32>().ok().filter(|it| (1..=10_000).contains(it)).ok_or("Precision must be between 1 and 10000 digits!")?;
+            num::set_precision(digits);
+            console.println(format!("Working precision is {} digits",digits));
+        }
+        ["depth"] => console.println(format!("Recursion depth is limited to {} calls",function::depth_limit())),
+        ["depth",calls] => {
+            let calls = calls.parse::<usize>().ok().filter(|it| (1..=10_000).contains(it)).ok_or("Depth must be between 1 and 10000 calls!")?;
+            function::set_depth_limit(calls);
+            console.println(format!("Recursion depth is limited to {} calls",calls));
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn solve_system<N:Numeric>(console: &mut ConsoleManager, functions: &Functions<N>, input: &str) -> Result<(),Box<dyn Error>>{
+    let mut evaluators = Vec::new();
+    for line in parser::statements(input).iter().filter(|it| !it.trim().is_empty()) {
+        let eq = parser::parse(line).map_err(|err| point_at(console, line, err, true))?;
+        console.println(eq.to_string());
+        evaluators.push(Evaluator::new(functions.inline_equation(eq)?)?);
     }
+    console.println("Compile...");
+    evaluators.iter_mut().for_each(|evaluator| evaluator.print(console));
+    LinearSystem::new(&evaluators)?.solve()?.print(console);
     Ok(())
 }
@@ -0,0 +1,213 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::eval::EvalError;
+use crate::numeric::Numeric;
+use crate::poly::{div, mul, sub};
+use crate::term::{Commutative, Term};
+
+/// Entries of a matrix row by row, every row of the same length.
+/// A vector is a matrix of a single row or column.
+pub type Rows<N> = Vec<Vec<Term<N>>>;
+
+/// Number of rows and columns.
+pub fn shape<N>(rows:&Rows<N>) -> (usize,usize) {
+    (rows.len(), rows.first().map(|it| it.len()).unwrap_or(0))
+}
+
+/// Why a matrix could not be built or combined, shapes given as `(rows, columns)`.
+#[derive(Debug)]
+pub enum MatrixError {
+    Empty,
+    Ragged,
+    ElementWise((usize,usize),(usize,usize)),
+    Multiply((usize,usize),(usize,usize)),
+    /// The operation named, such as `determinant`, on a matrix that is not square.
+    NotSquare(&'static str,(usize,usize)),
+    Singular,
+    /// A system `A @ x = b` whose right side has not as many rows as `A`.
+    Solve((usize,usize),(usize,usize)),
+    /// A matrix operation, such as `det(5)`, given a number.
+    NotMatrix(String),
+}
+
+impl Display for MatrixError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let describe = |(height,width):&(usize,usize)| format!("{}x{}",height,width);
+        match self {
+            Self::Empty => write!(f, "Empty matrix!"),
+            Self::Ragged => write!(f, "Rows of a matrix must have the same length!"),
+            Self::ElementWise(left,right) => write!(f, "Can not combine {} and {} matrices element-wise", describe(left), describe(right)),
+            Self::Multiply(left,right) => write!(f, "Can not multiply {} and {} matrices", describe(left), describe(right)),
+            Self::NotSquare(operation,shape) => write!(f, "Can not take the {} of a {} matrix", operation, describe(shape)),
+            Self::Singular => write!(f, "Matrix is singular!"),
+            Self::Solve(system,right) => write!(f, "Can not solve a {} system for a {} right side", describe(system), describe(right)),
+            Self::NotMatrix(call) => write!(f, "{} expects matrices, not numbers", call),
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+/// Checks a matrix literal is not empty and every row has the same length.
+pub fn check<N>(rows:&Rows<N>) -> Result<(),MatrixError> {
+    let (_,width) = shape(rows);
+    if width == 0 {
+        return Err(MatrixError::Empty);
+    }
+    if rows.iter().any(|row| row.len() != width) {
+        return Err(MatrixError::Ragged);
+    }
+    Ok(())
+}
+
+/// Applies `op` to every entry.
+pub fn map<N:Numeric>(rows:&Rows<N>, op:impl Fn(&Term<N>) -> Term<N>) -> Rows<N> {
+    rows.iter().map(|row| row.iter().map(&op).collect()).collect()
+}
+
+/// Pairs up the entries of two matrices of the same shape, for element-wise arithmetic.
+pub fn zip<N:Numeric>(left:&Rows<N>, right:&Rows<N>, op:impl Fn(&Term<N>,&Term<N>) -> Term<N>) -> Result<Rows<N>,MatrixError> {
+    if shape(left) != shape(right) {
+        return Err(MatrixError::ElementWise(shape(left), shape(right)));
+    }
+    Ok(left.iter().zip(right).map(|(l,r)| l.iter().zip(r).map(|(a,b)| op(a,b)).collect()).collect())
+}
+
+pub fn transpose<N:Numeric>(rows:&Rows<N>) -> Rows<N> {
+    let (height,width) = shape(rows);
+    (0..width).map(|col| (0..height).map(|row| rows[row][col].clone()).collect()).collect()
+}
+
+/// Matrix product, each entry left as a sum of products to be folded by the caller.
+pub fn multiply<N:Numeric>(left:&Rows<N>, right:&Rows<N>) -> Result<Rows<N>,MatrixError> {
+    let ((height,inner),(depth,width)) = (shape(left),shape(right));
+    if inner != depth {
+        return Err(MatrixError::Multiply(shape(left), shape(right)));
+    }
+    Ok((0..height).map(|row| (0..width).map(|col| Term::Chain(Commutative::Add, (0..inner)
+        .map(|k| Term::Chain(Commutative::Multiply, [left[row][k].clone(), right[k][col].clone()].into()))
+        .collect())).collect()).collect())
+}
+
+/// The entries as numbers, `None` while any of them is not one.
+pub(crate) fn numbers<N:Numeric>(rows:&Rows<N>) -> Option<Vec<Vec<N>>> {
+    rows.iter().map(|row| row.iter().map(|it| match it {
+        Term::Number(n) => Some(n.clone()),
+        _ => None,
+    }).collect()).collect()
+}
+
+fn check_square<N>(rows:&Rows<N>, operation:&'static str) -> Result<usize,MatrixError> {
+    let (height,width) = shape(rows);
+    if height != width {
+        return Err(MatrixError::NotSquare(operation, shape(rows)));
+    }
+    Ok(height)
+}
+
+/// Row with the largest entry in `col` from `from` down, the pivot that loses the least precision.
+fn pivot<N:Numeric>(a:&[Vec<N>], col:usize, from:usize) -> usize {
+    (from..a.len()).max_by(|&i,&j| a[i][col].abs().total_cmp(&a[j][col].abs())).unwrap_or(from)
+}
+
+/// Determinant by Gaussian elimination, `None` while an entry is not a number.
+pub fn det<N:Numeric>(rows:&Rows<N>) -> Result<Option<N>,EvalError> {
+    let size = check_square(rows, "determinant")?;
+    let Some(mut a) = numbers(rows) else { return Ok(None) };
+    let mut det = N::from(1);
+    for col in 0..size {
+        let pivot = pivot(&a, col, col);
+        if a[pivot][col].is_zero() {
+            return Ok(Some(N::from(0)));
+        }
+        if pivot != col {
+            a.swap(pivot, col);
+            det = -det;
+        }
+        det = mul(&det, &a[col][col])?;
+        let (upper,lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for row in lower {
+            let factor = div(&row[col], &pivot_row[col])?;
+            for (entry,above) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *entry = sub(entry, &mul(&factor, above)?)?;
+            }
+        }
+    }
+    Ok(Some(det))
+}
+
+/// Inverse by Gauss-Jordan elimination, `None` while an entry is not a number.
+pub fn inverse<N:Numeric>(rows:&Rows<N>) -> Result<Option<Rows<N>>,EvalError> {
+    let size = check_square(rows, "inverse")?;
+    let Some(mut a) = numbers(rows) else { return Ok(None) };
+    let mut inverse = (0..size).map(|row| (0..size).map(|col| N::from((row == col) as i32)).collect::<Vec<N>>()).collect::<Vec<Vec<N>>>();
+    for col in 0..size {
+        let pivot = pivot(&a, col, col);
+        if a[pivot][col].is_zero() {
+            return Err(MatrixError::Singular.into());
+        }
+        a.swap(pivot, col);
+        inverse.swap(pivot, col);
+        let scale = a[col][col].clone();
+        for entry in a[col].iter_mut().chain(inverse[col].iter_mut()) {
+            *entry = div(entry, &scale)?;
+        }
+        for row in (0..size).filter(|it| *it != col) {
+            let factor = a[row][col].clone();
+            for k in 0..size {
+                a[row][k] = sub(&a[row][k], &mul(&factor, &a[col][k])?)?;
+                inverse[row][k] = sub(&inverse[row][k], &mul(&factor, &inverse[col][k])?)?;
+            }
+        }
+    }
+    Ok(Some(inverse.into_iter().map(|row| row.into_iter().map(Term::Number).collect()).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use crate::complex::Complex;
+    use crate::eval::EvalError;
+    use crate::testing::{formula, value, N};
+
+    fn eval(s:&str) -> String {
+        value::<N>(s, &[]).unwrap_or_else(|err| err.to_string())
+    }
+
+    #[test]
+    fn operations() {
+        assert_eq!(eval("transpose([1, 2; 3, 4])"), "[1, 3; 2, 4]");
+        assert_eq!(eval("det([1, 2; 3, 4])"), "-2");
+        assert_eq!(eval("inv([1, 2; 3, 4])"), "[-2, 1; 1.5, -0.5]");
+        assert_eq!(eval("[1, 2; 3, 4] @ [1; 1]"), "[3; 7]");
+        assert_eq!(eval("det([1, 2; 2, 4] + [0, 0; 0, 1])"), "1");
+        assert_eq!(eval("inv([1, 2; 2, 4])"), "Matrix is singular!");
+        assert_eq!(eval("det([1, 2])"), "Can not take the determinant of a 1x2 matrix");
+    }
+
+    #[test]
+    fn out_of_range() {
+        let big = "1000000000000000000000";
+        let matrix = format!("[{big}, {big}; 3*{big}, 2*{big}]");
+        assert!(matches!(value::<Complex<Decimal>>(&format!("det({})",matrix), &[]), Err(EvalError::Overflow(_))));
+        assert!(matches!(value::<Complex<Decimal>>(&format!("solve({}, [1; 1])",matrix), &[]), Err(EvalError::Overflow(_))));
+        assert_eq!(value::<N>(&format!("det({})",matrix), &[]).unwrap(), "-1000000000000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn numbers_rejected() {
+        assert_eq!(eval("det(5)"), "det(5) expects matrices, not numbers");
+        assert_eq!(eval("inv(2)"), "inv(2) expects matrices, not numbers");
+        assert_eq!(eval("solve([1], 2)"), "solve([1], 2) expects matrices, not numbers");
+        assert_eq!(value::<N>("inv(x)", &[("x", 2)]).unwrap_err().to_string(), "inv(2) expects matrices, not numbers");
+    }
+
+    #[test]
+    fn entry_wise_products_kept_apart() {
+        assert_eq!(formula::<N>("[1, 2] * [1, 2]").simplify().to_string(), "[1, 2]*[1, 2]");
+        assert_eq!(formula::<N>("inv(A) * inv(A)").simplify().to_string(), "inv(A)*inv(A)");
+        assert_eq!(formula::<N>("det(A) * det(A)").simplify().to_string(), "det(A)^2");
+        assert_eq!(eval("[1, 2] * [1, 2]"), "[1, 4]");
+    }
+}
@@ -0,0 +1,412 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Pow, Signed, ToPrimitive, Zero};
+
+/// Significant digits irrational results are computed to, and decimals are printed with.
+static PRECISION:AtomicU32 = AtomicU32::new(28);
+
+/// Digits carried beyond the working precision, so rounding errors stay out of the printed result.
+const GUARD:u32 = 10;
+
+/// Roots of a higher degree than this are taken through `exp` and `ln` instead of an integer root.
+const ROOT_LIMIT:u32 = 64;
+
+pub fn precision() -> u32 {
+    PRECISION.load(Ordering::Relaxed)
+}
+
+pub fn set_precision(digits:u32) {
+    PRECISION.store(digits, Ordering::Relaxed);
+}
+
+/// Digits intermediate results are rounded to.
+pub(crate) fn working_digits() -> u32 {
+    precision() + GUARD
+}
+
+/// Most digits an exact result may have in its numerator and denominator together,
+/// so `2^2147483647` fails instead of running out of memory.
+pub(crate) fn digit_limit() -> u64 {
+    1000 * working_digits() as u64
+}
+
+/// Exact rational number, so `1/3*3` stays `1` instead of `0.999…`.
+/// Operations with an irrational result, such as `sqrt(2)`, are rounded to the working precision.
+#[derive(PartialEq,Eq,PartialOrd,Ord,Clone,Hash)]
+pub struct Num(BigRational);
+
+/// How numbers are printed, switched from the REPL.
+#[derive(PartialEq,Eq,Clone,Copy)]
+pub enum Notation {
+    /// Decimal expansion rounded to the working precision, `0.3333…`.
+    Decimal,
+    /// Reduced fraction, `1/3`.
+    Rational,
+}
+
+static NOTATION:AtomicU8 = AtomicU8::new(Notation::Decimal as u8);
+
+impl Notation {
+    pub fn from_name(name:&str) -> Option<Self> {
+        match name {
+            "decimal" => Some(Self::Decimal),
+            "rational" => Some(Self::Rational),
+            _ => None,
+        }
+    }
+
+    pub fn current() -> Self {
+        if NOTATION.load(Ordering::Relaxed) == Self::Rational as u8 { Self::Rational } else { Self::Decimal }
+    }
+
+    pub fn set(self) {
+        NOTATION.store(self as u8, Ordering::Relaxed);
+    }
+}
+
+impl Display for Notation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decimal => write!(f,"decimal"),
+            Self::Rational => write!(f,"rational"),
+        }
+    }
+}
+
+impl Num {
+    /// `numer / denom`, which must not be zero.
+    pub fn new(numer:i64, denom:i64) -> Self {
+        Self(BigRational::new(numer.into(), denom.into()))
+    }
+
+    /// `10^exp`
+    pub fn ten_pow(exp:i32) -> Self {
+        Self(Pow::pow(BigRational::from_integer(10.into()), exp))
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        self.0.numer()
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        self.0.denom()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.0.is_integer()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0.is_negative()
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.0.is_positive()
+    }
+
+    pub fn abs(&self) -> Self {
+        Self(self.0.abs())
+    }
+
+    pub fn signum(&self) -> Self {
+        Self(self.0.signum())
+    }
+
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.is_integer() { self.0.to_integer().to_i128() } else { None }
+    }
+
+    pub fn to_u32(&self) -> Option<u32> {
+        if self.is_integer() { self.0.to_integer().to_u32() } else { None }
+    }
+
+    pub fn to_usize(&self) -> Option<usize> {
+        if self.is_integer() { self.0.to_integer().to_usize() } else { None }
+    }
+
+    /// Nearest integer, halves rounded away from zero.
+    pub fn round(&self) -> Self {
+        Self(self.0.round())
+    }
+
+    /// Rounds to `dp` decimal places, a negative `dp` rounding to tens, hundreds, ….
+    pub fn round_dp(&self, dp:i32) -> Self {
+        let scale = Self::ten_pow(dp);
+        Self((&self.0 * &scale.0).round() / scale.0)
+    }
+
+    /// Rounds to `digits` significant digits.
+    pub fn round_sig(&self, digits:u32) -> Self {
+        if self.is_zero() {
+            return self.clone();
+        }
+        self.round_dp(digits as i32 - 1 - self.magnitude())
+    }
+
+    /// Position of the leading digit, `floor(log10(|self|))`, of a non-zero number.
+    pub fn magnitude(&self) -> i32 {
+        let digits = |n:&BigInt| n.magnitude().to_string().len() as i32;
+        let abs = self.abs();
+        let mut exp = digits(self.numer()) - digits(self.denom());
+        while abs < Self::ten_pow(exp) {
+            exp -= 1;
+        }
+        while abs >= Self::ten_pow(exp + 1) {
+            exp += 1;
+        }
+        exp
+    }
+
+    pub fn checked_div(&self, other:&Num) -> Option<Self> {
+        if other.is_zero() { None } else { Some(Self(&self.0 / &other.0)).filter(Self::within_limit) }
+    }
+
+    pub fn checked_mul(&self, other:&Num) -> Option<Self> {
+        Some(Self(&self.0 * &other.0)).filter(Self::within_limit)
+    }
+
+    /// Whether the numerator and denominator together stay within `digit_limit()` digits, at about 3.3 bits a digit.
+    fn within_limit(&self) -> bool {
+        (self.numer().bits() + self.denom().bits()) * 3 / 10 <= digit_limit()
+    }
+
+    /// `self^exp`, exact for integer exponents and for roots of perfect powers,
+    /// otherwise rounded to the working precision.
+    /// `None` when undefined, such as `0^-1` or `(-1)^(1/2)`.
+    pub fn pow(&self, exp:&Num) -> Option<Self> {
+        if exp.is_integer() {
+            let exp = exp.0.to_integer().to_i32()?;
+            if exp < 0 && self.is_zero() {
+                return None;
+            }
+            // The digits of the power are those of the base times the exponent, checked before computing it.
+            let bits = (self.numer().bits() + self.denom().bits()).saturating_sub(2);
+            if bits.saturating_mul(exp.unsigned_abs() as u64).saturating_mul(3) / 10 > digit_limit() {
+                return None;
+            }
+            return Some(Self(Pow::pow(&self.0, exp)));
+        }
+        match exp.denom().to_u32() {
+            Some(degree) if degree <= ROOT_LIMIT => self.root(degree)?.pow(&Self(BigRational::from_integer(exp.numer().clone()))),
+            _ if self.is_negative() => None,
+            _ if self.is_zero() => exp.is_positive().then(|| self.clone()),
+            _ => (exp * self.ln()?).exp(),
+        }
+    }
+
+    pub fn sqrt(&self) -> Option<Self> {
+        self.pow(&Self::new(1, 2))
+    }
+
+    /// Real `degree`-th root, exact when numerator and denominator are both perfect powers.
+    fn root(&self, degree:u32) -> Option<Self> {
+        if self.is_negative() && degree.is_multiple_of(2) {
+            return None;
+        }
+        let (numer,denom) = (self.numer().abs(),self.denom());
+        let exact = |n:&BigInt| {
+            let root = n.nth_root(degree);
+            if Pow::pow(&root, degree) == *n { Some(root) } else { None }
+        };
+        let root = match (exact(&numer),exact(denom)) {
+            (Some(numer),Some(denom)) => Self(BigRational::new(numer, denom)),
+            _ => {
+                // root(n/d) = root(n * d^(degree-1)) / d, scaled up by enough places for the working precision.
+                let places = working_digits() + (self.magnitude().min(0).unsigned_abs() / degree) + 1;
+                let scale = Pow::pow(BigInt::from(10), places);
+                let radicand = numer * Pow::pow(denom, degree - 1) * Pow::pow(&scale, degree);
+                Self(BigRational::new(radicand.nth_root(degree), denom * scale)).round_sig(working_digits())
+            }
+        };
+        Some(if self.is_negative() { -root } else { root })
+    }
+
+    /// Integers are printed in full, other numbers to the working precision,
+    /// in scientific notation when very small or too large for it.
+    fn fmt_decimal(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_integer() {
+            return write!(f,"{}",self.numer());
+        }
+        let digits = precision();
+        let rounded = self.round_sig(digits);
+        let exp = rounded.magnitude();
+        if exp < -7 || exp >= digits as i32 {
+            let mantissa = rounded / Self::ten_pow(exp);
+            return write!(f,"{}e{}",mantissa.fixed(digits - 1),exp);
+        }
+        write!(f,"{}",rounded.fixed((digits as i32 - 1 - exp) as u32))
+    }
+
+    /// Decimal expansion with at most `places` places, trailing zeros dropped.
+    fn fixed(&self, places:u32) -> String {
+        let digits = (self.abs() * Self::ten_pow(places as i32)).round().numer().to_string();
+        let digits = format!("{:0>width$}",digits,width = places as usize + 1);
+        let (int,frac) = digits.split_at(digits.len() - places as usize);
+        let sign = if self.is_negative() { "-" } else { "" };
+        match frac.trim_end_matches('0') {
+            "" => format!("{}{}",sign,int),
+            frac => format!("{}{}.{}",sign,int,frac),
+        }
+    }
+}
+
+impl Display for Num {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match Notation::current() {
+            Notation::Decimal => self.fmt_decimal(f),
+            Notation::Rational => write!(f,"{}",self.0),
+        }
+    }
+}
+
+impl Debug for Num {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{}",self)
+    }
+}
+
+/// Reads `12`, `1.25` and scientific notation such as `6.62607015e-34`, exactly,
+/// refusing exponents with more digits than `digit_limit()`.
+impl FromStr for Num {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid Number {}!",s);
+        let (mantissa,exp) = match s.split_once(['e','E']) {
+            Some((mantissa,exp)) => (mantissa,exp.parse::<i32>().ok().filter(|it| it.unsigned_abs() as u64 <= digit_limit()).ok_or_else(invalid)?),
+            None => (s,0),
+        };
+        let (int,frac) = mantissa.split_once('.').unwrap_or((mantissa,""));
+        if int.trim_start_matches('-').is_empty() && frac.is_empty() {
+            return Err(invalid());
+        }
+        let digits = BigInt::from_str(&format!("{}{}",int,frac)).map_err(|_| invalid())?;
+        Ok(Self(BigRational::from_integer(digits)) * Self::ten_pow(exp - frac.len() as i32))
+    }
+}
+
+impl Default for Num {
+    fn default() -> Self {
+        Self::from(0)
+    }
+}
+
+macro_rules! from_integer {
+    ($($int:ty),*) => {
+        $(impl From<$int> for Num {
+            fn from(n:$int) -> Self {
+                Self(BigRational::from_integer(n.into()))
+            }
+        })*
+    };
+}
+
+from_integer!(i32, i64, i128, u32, usize, BigInt);
+
+macro_rules! binary_op {
+    ($op:ident, $method:ident, $assign:ident, $assign_method:ident) => {
+        impl $op for Num {
+            type Output = Num;
+            fn $method(self, rhs:Num) -> Num { Num(self.0.$method(rhs.0)) }
+        }
+        impl $op<&Num> for Num {
+            type Output = Num;
+            fn $method(self, rhs:&Num) -> Num { Num(self.0.$method(&rhs.0)) }
+        }
+        impl $op<Num> for &Num {
+            type Output = Num;
+            fn $method(self, rhs:Num) -> Num { Num((&self.0).$method(rhs.0)) }
+        }
+        impl $op<&Num> for &Num {
+            type Output = Num;
+            fn $method(self, rhs:&Num) -> Num { Num((&self.0).$method(&rhs.0)) }
+        }
+        impl $assign for Num {
+            fn $assign_method(&mut self, rhs:Num) { self.0.$assign_method(rhs.0) }
+        }
+        impl $assign<&Num> for Num {
+            fn $assign_method(&mut self, rhs:&Num) { self.0.$assign_method(&rhs.0) }
+        }
+    };
+}
+
+binary_op!(Add, add, AddAssign, add_assign);
+binary_op!(Sub, sub, SubAssign, sub_assign);
+binary_op!(Mul, mul, MulAssign, mul_assign);
+binary_op!(Div, div, DivAssign, div_assign);
+
+impl Neg for Num {
+    type Output = Num;
+    fn neg(self) -> Num { Num(-self.0) }
+}
+
+impl Neg for &Num {
+    type Output = Num;
+    fn neg(self) -> Num { Num(-&self.0) }
+}
+
+impl Sum for Num {
+    fn sum<I: Iterator<Item=Num>>(iter: I) -> Self {
+        iter.fold(Num::from(0), |acc,it| acc + it)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use super::{Notation, Num};
+    use crate::complex::Complex;
+    use crate::term::Term;
+    use crate::testing::{evaluator, value, N};
+
+    #[test]
+    fn exact_powers() {
+        assert_eq!(Num::from(2).pow(&Num::from(10)), Some(Num::from(1024)));
+        assert_eq!(Num::new(2, 3).pow(&Num::from(-2)), Some(Num::new(9, 4)));
+        assert_eq!(Num::from(1).pow(&Num::from(i32::MAX)), Some(Num::from(1)));
+        assert_eq!(Num::from(0).pow(&Num::from(-1)), None);
+    }
+
+    #[test]
+    fn huge_powers() {
+        assert!(Num::from(2).pow(&Num::from(10_000)).is_some());
+        assert_eq!(Num::from(2).pow(&Num::from(i32::MAX)), None);
+        assert_eq!(Num::new(3, 2).pow(&Num::from(-1_000_000)), None);
+        let big = Num::from(10).pow(&Num::from(30_000)).unwrap();
+        assert_eq!(big.checked_mul(&big), None);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(Num::from_str("1.25e2"), Ok(Num::from(125)));
+        assert_eq!(Num::from_str("6.62607015e-34").unwrap(), Num::new(662607015, 1) * Num::ten_pow(-42));
+        assert!(Num::from_str("1e999999999").is_err());
+        assert!(Num::from_str("1e-999999999").is_err());
+    }
+
+    #[test]
+    fn exact_division() {
+        assert_eq!(value::<N>("1/3*3", &[]).unwrap(), "1");
+        assert_eq!(value::<N>("x/7*7 - x", &[("x",5)]).unwrap(), "0");
+        let third = evaluator::<N>("1/3").unwrap().eval(&HashMap::new()).unwrap();
+        assert_eq!(third, Term::Number(Complex::new(Num::new(1, 3), Num::from(0))));
+        assert_eq!(value::<N>("1/3", &[]).unwrap(), "0.3333333333333333333333333333");
+    }
+
+    #[test]
+    fn notations() {
+        assert!(Notation::from_name("rational") == Some(Notation::Rational));
+        assert!(Notation::from_name("decimal") == Some(Notation::Decimal));
+        assert!(Notation::from_name("fraction").is_none());
+    }
+}
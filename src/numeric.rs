@@ -0,0 +1,516 @@
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+use num_traits::ToPrimitive;
+use rust_decimal::{Decimal, MathematicalOps};
+use crate::eval::EvalError;
+use crate::num::{self, Num};
+use crate::poly::Polynomial;
+
+/// Number type the engine runs on: terms, evaluation and parsing are generic over it.
+/// Arithmetic goes through the operators, taking the right operand by value or by reference.
+pub trait Numeric: Clone + Default + PartialEq + PartialOrd + Display + Debug + From<i32> + Send + Sync
+    + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Div<Output=Self> + Neg<Output=Self>
+    + for<'a> Add<&'a Self, Output=Self> + for<'a> Sub<&'a Self, Output=Self>
+    + for<'a> Mul<&'a Self, Output=Self> + for<'a> Div<&'a Self, Output=Self>
+    + AddAssign + SubAssign + MulAssign + DivAssign
+    + for<'a> AddAssign<&'a Self> + for<'a> SubAssign<&'a Self>
+    + for<'a> MulAssign<&'a Self> + for<'a> DivAssign<&'a Self>
+{
+    /// Reads `12`, `1.25` or `6.62607015e-34`, `None` when not a number or out of range.
+    fn parse(s:&str) -> Option<Self>;
+    fn from_i128(n:i128) -> Option<Self>;
+    fn to_i128(&self) -> Option<i128>;
+
+    fn to_u32(&self) -> Option<u32> {
+        self.to_i128().and_then(|it| it.try_into().ok())
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        self.to_i128().and_then(|it| it.try_into().ok())
+    }
+
+    /// `numer / denom` with the same value, when both fit.
+    fn to_fraction(&self) -> Option<(i128,i128)>;
+
+    fn is_zero(&self) -> bool;
+    fn is_integer(&self) -> bool;
+    fn is_negative(&self) -> bool;
+    fn is_positive(&self) -> bool;
+    fn abs(&self) -> Self;
+
+    /// `None` when the absolute value is out of range, as the modulus of a large complex number can be.
+    fn checked_abs(&self) -> Option<Self> {
+        Some(self.abs())
+    }
+    fn signum(&self) -> Self;
+    /// Total order, so numbers can key maps and sort terms.
+    fn total_cmp(&self, other:&Self) -> Ordering;
+    fn round_dp(&self, dp:u32) -> Self;
+    /// Width below which two numbers are not told apart when searching for a root.
+    fn resolution() -> Self;
+    /// Drops the digits of an approximate result the number type does not carry.
+    fn round_working(&self) -> Self;
+
+    /// `None` when the sum is out of range, which only fixed-width number types run into.
+    fn checked_add(&self, other:&Self) -> Option<Self> {
+        Some(self.clone() + other)
+    }
+
+    fn checked_sub(&self, other:&Self) -> Option<Self> {
+        Some(self.clone() - other)
+    }
+
+    fn checked_mul(&self, other:&Self) -> Option<Self> {
+        Some(self.clone() * other)
+    }
+
+    /// `None` when dividing by zero or out of range.
+    fn checked_div(&self, other:&Self) -> Option<Self>;
+    /// `None` where undefined, such as `0^-1` or `(-1)^(1/2)`.
+    fn pow(&self, exp:&Self) -> Option<Self>;
+    fn sqrt(&self) -> Option<Self>;
+    fn exp(&self) -> Option<Self>;
+    fn ln(&self) -> Option<Self>;
+    fn sin(&self) -> Option<Self>;
+    fn cos(&self) -> Option<Self>;
+    fn tan(&self) -> Option<Self>;
+    fn atan(&self) -> Option<Self>;
+    fn pi() -> Self;
+
+    /// `i`, for number types that have it.
+    fn imaginary_unit() -> Option<Self> {
+        None
+    }
+
+    fn is_real(&self) -> bool {
+        true
+    }
+
+    /// Every root of `poly`, each repeated by its multiplicity.
+    fn polynomial_roots(poly:&Polynomial<Self>) -> Result<Vec<Self>,EvalError> {
+        poly.real_roots()
+    }
+}
+
+/// Wraps a number to key ordered maps through `Numeric::total_cmp`.
+#[derive(Clone,Debug)]
+pub struct Ordered<N>(pub N);
+
+impl<N:Numeric> PartialEq for Ordered<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl<N:Numeric> Eq for Ordered<N> {}
+
+impl<N:Numeric> PartialOrd for Ordered<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N:Numeric> Ord for Ordered<N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Numeric for Num {
+    fn parse(s:&str) -> Option<Self> {
+        Num::from_str(s).ok()
+    }
+
+    fn from_i128(n:i128) -> Option<Self> {
+        Some(Num::from(n))
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        Num::to_i128(self)
+    }
+
+    fn to_fraction(&self) -> Option<(i128,i128)> {
+        Some((self.numer().to_i128()?,self.denom().to_i128()?))
+    }
+
+    fn is_zero(&self) -> bool {
+        Num::is_zero(self)
+    }
+
+    fn is_integer(&self) -> bool {
+        Num::is_integer(self)
+    }
+
+    fn is_negative(&self) -> bool {
+        Num::is_negative(self)
+    }
+
+    fn is_positive(&self) -> bool {
+        Num::is_positive(self)
+    }
+
+    fn abs(&self) -> Self {
+        Num::abs(self)
+    }
+
+    fn signum(&self) -> Self {
+        Num::signum(self)
+    }
+
+    fn total_cmp(&self, other:&Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn round_dp(&self, dp:u32) -> Self {
+        Num::round_dp(self, dp as i32)
+    }
+
+    fn resolution() -> Self {
+        Num::ten_pow(-(num::working_digits() as i32))
+    }
+
+    fn round_working(&self) -> Self {
+        self.round_sig(num::working_digits())
+    }
+
+    fn checked_mul(&self, other:&Self) -> Option<Self> {
+        Num::checked_mul(self, other)
+    }
+
+    fn checked_div(&self, other:&Self) -> Option<Self> {
+        Num::checked_div(self, other)
+    }
+
+    fn pow(&self, exp:&Self) -> Option<Self> {
+        Num::pow(self, exp)
+    }
+
+    fn sqrt(&self) -> Option<Self> {
+        Num::sqrt(self)
+    }
+
+    fn exp(&self) -> Option<Self> {
+        Num::exp(self)
+    }
+
+    fn ln(&self) -> Option<Self> {
+        Num::ln(self)
+    }
+
+    fn sin(&self) -> Option<Self> {
+        Some(Num::sin(self))
+    }
+
+    fn cos(&self) -> Option<Self> {
+        Some(Num::cos(self))
+    }
+
+    fn tan(&self) -> Option<Self> {
+        Num::tan(self)
+    }
+
+    fn atan(&self) -> Option<Self> {
+        Some(Num::atan(self))
+    }
+
+    fn pi() -> Self {
+        Num::pi()
+    }
+}
+
+/// Fixed 28 digit decimals, the original backend.
+impl Numeric for Decimal {
+    fn parse(s:&str) -> Option<Self> {
+        Decimal::from_str(s).or_else(|_| Decimal::from_scientific(s)).ok()
+    }
+
+    fn from_i128(n:i128) -> Option<Self> {
+        Decimal::try_from_i128_with_scale(n, 0).ok()
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        if self.is_integer() { ToPrimitive::to_i128(self) } else { None }
+    }
+
+    fn to_fraction(&self) -> Option<(i128,i128)> {
+        Some((self.mantissa(),10i128.checked_pow(self.scale())?))
+    }
+
+    fn is_zero(&self) -> bool {
+        Decimal::is_zero(self)
+    }
+
+    fn is_integer(&self) -> bool {
+        Decimal::is_integer(self)
+    }
+
+    fn is_negative(&self) -> bool {
+        self.is_sign_negative() && !self.is_zero()
+    }
+
+    fn is_positive(&self) -> bool {
+        self.is_sign_positive() && !self.is_zero()
+    }
+
+    fn abs(&self) -> Self {
+        Decimal::abs(self)
+    }
+
+    fn signum(&self) -> Self {
+        if self.is_zero() { Decimal::ZERO } else { num_traits::Signed::signum(self) }
+    }
+
+    fn total_cmp(&self, other:&Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn round_dp(&self, dp:u32) -> Self {
+        Decimal::round_dp(self, dp)
+    }
+
+    fn resolution() -> Self {
+        Decimal::new(1, 25)
+    }
+
+    fn round_working(&self) -> Self {
+        *self
+    }
+
+    fn checked_add(&self, other:&Self) -> Option<Self> {
+        Decimal::checked_add(*self, *other)
+    }
+
+    fn checked_sub(&self, other:&Self) -> Option<Self> {
+        Decimal::checked_sub(*self, *other)
+    }
+
+    fn checked_mul(&self, other:&Self) -> Option<Self> {
+        Decimal::checked_mul(*self, *other)
+    }
+
+    fn checked_div(&self, other:&Self) -> Option<Self> {
+        Decimal::checked_div(*self, *other)
+    }
+
+    fn pow(&self, exp:&Self) -> Option<Self> {
+        if Numeric::is_zero(self) && Numeric::is_negative(exp) {
+            return None;
+        }
+        match Numeric::to_i128(exp).and_then(|it| i64::try_from(it).ok()) {
+            Some(exp) => self.checked_powi(exp),
+            None if *exp == Decimal::new(5, 1) => Numeric::sqrt(self),
+            None if Numeric::is_negative(self) => None,
+            // `checked_powd` goes through the inaccurate series behind `checked_exp`.
+            None => Numeric::exp(&self.checked_ln()?.checked_mul(*exp)?),
+        }
+    }
+
+    fn sqrt(&self) -> Option<Self> {
+        MathematicalOps::sqrt(self)
+    }
+
+    /// The series behind `checked_exp` only converges for small arguments,
+    /// so the argument is halved below 1 and the result squared back up.
+    fn exp(&self) -> Option<Self> {
+        let mut reduced = *self;
+        let mut halvings = 0;
+        while reduced.abs() > Decimal::ONE {
+            reduced /= Decimal::TWO;
+            halvings += 1;
+        }
+        let mut result = reduced.checked_exp_with_tolerance(Decimal::new(1, 27))?;
+        for _ in 0..halvings {
+            result = result.checked_mul(result)?;
+        }
+        Some(result)
+    }
+
+    fn ln(&self) -> Option<Self> {
+        self.checked_ln()
+    }
+
+    fn sin(&self) -> Option<Self> {
+        self.checked_sin()
+    }
+
+    fn cos(&self) -> Option<Self> {
+        self.checked_cos()
+    }
+
+    fn tan(&self) -> Option<Self> {
+        self.checked_tan()
+    }
+
+    /// Halves the angle through `atan(x) = 2 atan(x / (1 + sqrt(1 + x^2)))` until the series converges quickly,
+    /// large arguments first folded in by `atan(x) = ±π/2 - atan(1/x)`.
+    fn atan(&self) -> Option<Self> {
+        if self.abs() > Decimal::ONE {
+            let folded = Numeric::atan(&(Decimal::ONE / self))?;
+            return Some(Decimal::HALF_PI * Numeric::signum(self) - folded);
+        }
+        let mut x = *self;
+        let mut halvings = 0;
+        while x.abs() > Decimal::new(125, 3) {
+            x /= Decimal::ONE + MathematicalOps::sqrt(&(Decimal::ONE + x * x))?;
+            halvings += 1;
+        }
+        let square = x * x;
+        let mut sum = x;
+        let mut power = x;
+        let mut n = 0;
+        loop {
+            power = -power * square;
+            n += 1;
+            let term = power / Decimal::from(2 * n + 1);
+            if term.is_zero() {
+                break;
+            }
+            sum += term;
+        }
+        Some(sum * Decimal::from(1 << halvings))
+    }
+
+    fn pi() -> Self {
+        Decimal::PI
+    }
+}
+
+/// Hardware floats, fast but rounding at every step.
+impl Numeric for f64 {
+    fn parse(s:&str) -> Option<Self> {
+        s.parse::<f64>().ok().filter(|it| it.is_finite())
+    }
+
+    fn from_i128(n:i128) -> Option<Self> {
+        Some(n as f64)
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        if self.is_integer() { ToPrimitive::to_i128(self) } else { None }
+    }
+
+    fn to_fraction(&self) -> Option<(i128,i128)> {
+        // Every finite float is a dyadic fraction, exact once scaled by a power of two.
+        let mut numer = *self;
+        let mut denom = 1i128;
+        while !numer.is_integer() {
+            numer *= 2.0;
+            denom = denom.checked_mul(2)?;
+        }
+        Some((Numeric::to_i128(&numer)?,denom))
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0.0
+    }
+
+    fn is_integer(&self) -> bool {
+        self.is_finite() && self.fract() == 0.0
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < 0.0
+    }
+
+    fn is_positive(&self) -> bool {
+        *self > 0.0
+    }
+
+    fn abs(&self) -> Self {
+        f64::abs(*self)
+    }
+
+    fn signum(&self) -> Self {
+        if self.is_zero() { 0.0 } else { f64::signum(*self) }
+    }
+
+    fn total_cmp(&self, other:&Self) -> Ordering {
+        f64::total_cmp(self, other)
+    }
+
+    fn round_dp(&self, dp:u32) -> Self {
+        let scale = 10f64.powi(dp as i32);
+        (self * scale).round() / scale
+    }
+
+    /// Bisection stops on its own once the midpoint can no longer be told from the ends.
+    fn resolution() -> Self {
+        0.0
+    }
+
+    fn round_working(&self) -> Self {
+        *self
+    }
+
+    fn checked_div(&self, other:&Self) -> Option<Self> {
+        if other.is_zero() { None } else { Some(self / other) }
+    }
+
+    fn pow(&self, exp:&Self) -> Option<Self> {
+        let result = match Numeric::to_i128(exp).and_then(|it| i32::try_from(it).ok()) {
+            Some(exp) => self.powi(exp),
+            None => self.powf(*exp),
+        };
+        result.is_finite().then_some(result)
+    }
+
+    fn sqrt(&self) -> Option<Self> {
+        (*self >= 0.0).then(|| f64::sqrt(*self))
+    }
+
+    fn exp(&self) -> Option<Self> {
+        Some(f64::exp(*self)).filter(|it| it.is_finite())
+    }
+
+    fn ln(&self) -> Option<Self> {
+        (*self > 0.0).then(|| f64::ln(*self))
+    }
+
+    fn sin(&self) -> Option<Self> {
+        Some(f64::sin(*self))
+    }
+
+    fn cos(&self) -> Option<Self> {
+        Some(f64::cos(*self))
+    }
+
+    fn tan(&self) -> Option<Self> {
+        Some(f64::tan(*self)).filter(|it| it.is_finite())
+    }
+
+    fn atan(&self) -> Option<Self> {
+        Some(f64::atan(*self))
+    }
+
+    fn pi() -> Self {
+        std::f64::consts::PI
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use crate::complex::Complex;
+    use crate::eval::EvalError;
+    use crate::testing::{value, N};
+
+    #[test]
+    fn backends() {
+        let formula = "(x^2 - 1)*3 - x";
+        assert_eq!(value::<N>(formula, &[("x",4)]).unwrap(), "41");
+        assert_eq!(value::<Complex<Decimal>>(formula, &[("x",4)]).unwrap(), "41");
+        assert_eq!(value::<Complex<f64>>(formula, &[("x",4)]).unwrap(), "41");
+        assert_eq!(value::<N>("1/4", &[]).unwrap(), "0.25");
+        assert_eq!(value::<Complex<Decimal>>("1/4", &[]).unwrap(), "0.25");
+        assert_eq!(value::<Complex<f64>>("1/4", &[]).unwrap(), "0.25");
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(matches!(value::<N>("1/x", &[("x",0)]), Err(EvalError::DivisionByZero)));
+        assert!(matches!(value::<Complex<Decimal>>("1/x", &[("x",0)]), Err(EvalError::DivisionByZero)));
+        assert!(matches!(value::<Complex<f64>>("1/x", &[("x",0)]), Err(EvalError::DivisionByZero)));
+    }
+}
@@ -1,211 +1,692 @@
 use std::cmp::PartialEq;
-use std::collections::VecDeque;
 use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 
-use crate::console::ConsoleManager;
+use crate::numeric::Numeric;
 use crate::term::*;
+use crate::matrix;
+use crate::unit::Unit;
 
+/// What went wrong while parsing.
+#[derive(Debug,Clone,PartialEq)]
+pub enum ParseErrorKind {
+    InvalidCharacter(char),
+    InvalidNumber(String),
+    /// A number such as `012`, only `0.` may start with a zero.
+    LeadingZero(String),
+    UnknownUnits(String),
+    /// An opening parenthesis or bracket that is never closed.
+    Unclosed(char),
+    /// A closing parenthesis or bracket that was never opened.
+    Unmatched(char),
+    /// An operator or the end of the input where a number, name or group should be.
+    MissingOperand,
+    /// Two operands next to each other with no operator between them.
+    MissingOperator,
+    /// A third side of an equation.
+    TooManySides,
+    /// An equation given as a function argument or matrix entry.
+    NestedEquation,
+    /// A call with the wrong number of arguments.
+    Arity { name: String, expected: &'static str, found: usize },
+    InvalidMatrix(String),
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCharacter(c) => write!(f, "Invalid Character '{}'", c),
+            Self::InvalidNumber(number) => write!(f, "Invalid Number {}!", number),
+            Self::LeadingZero(number) => write!(f, "Invalid Number {}! Cant start with 0 !", number),
+            Self::UnknownUnits(units) => write!(f, "Unknown units {}", units),
+            Self::Unclosed('[') => write!(f, "Brackets not closed!"),
+            Self::Unclosed(_) => write!(f, "Parentheses not closed!"),
+            Self::Unmatched(c) => write!(f, "Unmatched '{}'", c),
+            Self::MissingOperand => write!(f, "No Terms Found!"),
+            Self::MissingOperator => write!(f, "Invalid Expr!"),
+            Self::TooManySides => write!(f, "Equation already has two sides!"),
+            Self::NestedEquation => write!(f, "An argument or matrix entry can not be an equation!"),
+            Self::Arity { name, expected, found } => write!(f, "{} expects {} argument{}, got {}",
+                name, expected, if *expected == "1" { "" } else { "s" }, found),
+            Self::InvalidMatrix(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// A parse failure, with the byte span of the input it is about and what would have been accepted there.
+#[derive(Debug,Clone,PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Range<usize>,
+    pub expected: Option<&'static str>,
+}
+
+impl ParseError {
+    fn new(kind:ParseErrorKind, span:Range<usize>) -> Self {
+        Self { kind, span, expected: None }
+    }
+
+    fn expecting(mut self, expected:&'static str) -> Self {
+        self.expected = Some(expected);
+        self
+    }
+
+    /// A line of `^` under the characters of `input` the error is about, to be printed below it.
+    pub fn pointer(&self, input:&str) -> String {
+        let column = |at:usize| input.get(..at).unwrap_or(input).chars().count();
+        let (start,end) = (column(self.span.start), column(self.span.end));
+        " ".repeat(start) + &"^".repeat((end - start).max(1))
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(expected) = self.expected {
+            write!(f, " (expected {})", expected)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {}
+
+const OPERAND:&str = "a number, name or '('";
 
-struct Parser {
-    terms: VecDeque<Term>,
-    mode: ParseMode,
-    last_priority: i32,
-    paren: i32,
-    builder:Vec<char>,
-    equation: Equation,
+/// Characters of the input, each with the byte offset it was read from,
+/// so errors point into the input however the text was split or rewritten.
+#[derive(Clone,Copy)]
+struct Source<'a> {
+    chars: &'a [char],
+    at: &'a [usize],
+    /// Byte offset right after the text.
+    end: usize,
 }
 
-impl Parser {
-    fn new() -> Self {
-        Self{
-            terms: VecDeque::new(),
-            mode: ParseMode::None,
-            last_priority:-1,
-            paren:0,
-            builder:Vec::new(),
-            equation: Equation(None,None)
+impl<'a> Source<'a> {
+    fn slice(&self, range:Range<usize>) -> Source<'a> {
+        let end = self.at.get(range.end).copied().unwrap_or(self.end);
+        Source { chars: &self.chars[range.clone()], at: &self.at[range], end }
+    }
+
+    fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    /// Byte span of the characters in `range`.
+    fn span(&self, range:Range<usize>) -> Range<usize> {
+        let start = self.at.get(range.start).copied().unwrap_or(self.end);
+        match range.end.checked_sub(1).filter(|last| *last >= range.start) {
+            Some(last) => start..self.at[last] + self.chars[last].len_utf8(),
+            None => start..start,
         }
     }
 
-    fn print(&mut self,console:&mut ConsoleManager){
-        console.print("Terms: ");
-        console.println(format!("{}",self.terms.len()));
-        self.terms.iter().for_each(|t| console.println(t.to_string()));
+    fn error(&self, kind:ParseErrorKind, range:Range<usize>) -> ParseError {
+        ParseError::new(kind, self.span(range))
+    }
+
+    fn text(&self, range:Range<usize>) -> String {
+        self.chars[range].iter().collect()
     }
-    fn builder_end(&mut self)-> Result<(),Box<dyn Error>>{
-        match self.mode {
-            ParseMode::Number => {
-                let num_str = self.builder.iter().rev().collect::<String>();
-                if num_str.len() > 1 && num_str.starts_with("0") && num_str.get(1..=1).ne(&Some(".")) {
-                    return Err("Invalid Number! Cant start with 0 !".into());
+}
+
+/// A token of the input.
+#[derive(Clone)]
+enum Token<N> {
+    Number(N),
+    Name(String),
+    /// Units in brackets, such as `[h]` or `[m/s^2]`, kept apart from unknowns and constants of the same name.
+    Units(Term<N>),
+    Operator(Operator),
+    Relation(Relation),
+    /// `(` or `[`.
+    Open(char),
+    /// `)` or `]`.
+    Close(char),
+    Comma,
+    Semicolon,
+}
+
+#[derive(Clone)]
+struct Spanned<N> {
+    token: Token<N>,
+    /// Byte span of the token in the input.
+    span: Range<usize>,
+}
+
+/// Splits the input into tokens.
+fn tokenize<N:Numeric>(src:Source) -> Result<Vec<Spanned<N>>,ParseError>{
+    let chars = src.chars;
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        i += 1;
+        let token = match c {
+            ' ' => continue,
+            '0'..='9' | '.' => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
                 }
-                if num_str.matches("\\.").count() > 1 {
-                    return Err("Invalid Number! Multiple Decimals!".into());
+                let text = src.text(start..i);
+                if text.len() > 1 && text.starts_with('0') && !text.starts_with("0.") {
+                    return Err(src.error(ParseErrorKind::LeadingZero(text), start..i));
                 }
-                let number = num_str.parse::<Num>()?;
-                self.builder.clear();
-                self.mode = ParseMode::None;
-                self.terms.push_back(Term::Number(number));
+                Token::Number(N::parse(&text).ok_or_else(|| src.error(ParseErrorKind::InvalidNumber(text), start..i))?)
             }
-            ParseMode::Literal => {
-                let literal = self.builder.iter().rev().collect::<String>();
-                self.builder.clear();
-                self.mode = ParseMode::None;
-                self.terms.push_back(Term::Unknown(literal));
+            'A'..='Z' | 'a'..='z' => {
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                Token::Name(src.text(start..i))
             }
-            _ => {}
-        }
-        Ok(())
-    }
-    fn end(&mut self)-> Result<(),Box<dyn Error>>{
-        self.builder_end()?;
-        if self.paren != 0 {
-            return Err("Parentheses not closed!".into());
-        }
-        let mut count = 0;
-        let len = self.terms.len();
-        while self.terms.len() >= 2 {
-            count += 1;
-            let mut term = self.terms.pop_back().ok_or("No Terms Found!")?;
-            let complex =  self.terms.back_mut().ok_or("No Chain / Power Found!")?;
-            match (&mut term,complex) {
-                (Term::Chain(com_pop,terms_pop),Term::Chain(com_last,terms_last)) =>
-                    match (com_last,com_pop) {
-                        (Commutative::Multiply,Commutative::Multiply) |
-                        (Commutative::Add,Commutative::Add) => terms_last.append(terms_pop),
-                        (Commutative::Multiply,Commutative::Add) =>
-                            if terms_pop.len() == 1 {
-                                terms_last.push_back(terms_pop.pop_back().unwrap());
-                            } else {
-                                terms_last.push_back(term);
-                            }
-                        (Commutative::Add,Commutative::Multiply) => { self.terms.push_front(term);count += 1; },
-                    }
-                (_,Term::Power(base,_)) => {
-                    if base.is_some() {
-                        return Err("Power already has a base!".into());
-                    }
-                    *base = Some(term.into());
+            '[' => match bracketed_units(chars, i) {
+                Some(end) => {
+                    let units = units(src.slice(i..end))?;
+                    i = end + 1;
+                    Token::Units(units)
                 }
-                (_,Term::Chain(_,terms)) => {
-                    terms.push_back(term);
+                None => Token::Open(c),
+            },
+            '(' => Token::Open(c),
+            ')' | ']' => Token::Close(c),
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            _ => {
+                let relation = Relation::SYMBOLS.iter().find(|(symbol,_)| chars[start..].starts_with(&symbol.chars().collect::<Vec<char>>()));
+                match (relation,Operator::from_char(c)) {
+                    (Some((symbol,relation)),_) => {
+                        i = start + symbol.len();
+                        Token::Relation(*relation)
+                    }
+                    (None,Some(operator)) => Token::Operator(operator),
+                    (None,None) => return Err(src.error(ParseErrorKind::InvalidCharacter(c), start..i).expecting("an operator or operand")),
                 }
-                _ => return Err("Invalid Expr!".into())
             }
-            if count >= len { break; }
+        };
+        tokens.push(Spanned { token, span: src.span(start..i) });
+    }
+    Ok(tokens)
+}
+
+/// End of the units starting at `from` and closed by `]`: names joined by `*` and `/`, each with an optional
+/// integer exponent, as in `[kg*m/s^2]`. Such a bracket always holds units, so `[x]` is reported as unknown units
+/// rather than read as a matrix, which `[(x)]` is.
+fn bracketed_units(chars:&[char], from:usize) -> Option<usize> {
+    let mut at = from;
+    loop {
+        let name = chars[at..].iter().take_while(|c| c.is_ascii_alphabetic()).count();
+        if name == 0 {
+            return None;
         }
-        if self.terms.len() > 1 {
-            let mut deque = VecDeque::new();
-            deque.append(&mut self.terms);
-            self.terms.push_back(Term::Chain(Commutative::Add,deque));
+        at += name;
+        if chars.get(at) == Some(&'^') {
+            at += 1;
+            if chars.get(at) == Some(&'-') {
+                at += 1;
+            }
+            let digits = chars[at..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 {
+                return None;
+            }
+            at += digits;
         }
-        match self.equation {
-            Equation(_,None) =>  self.equation.1 = Some(self.terms.pop_back().ok_or("No Terms Found!")?),
-            _ => self.equation.0 = Some(self.terms.pop_back().ok_or("No Terms Found!")?),
+        match chars.get(at) {
+            Some(']') => return Some(at),
+            Some('*' | '/') => at += 1,
+            _ => return None,
         }
-        Ok(())
     }
+}
 
-    fn parse(&mut self, c:char) -> Result<(),Box<dyn Error>> {
+/// End of the unit with an optional integer exponent starting at `from`, such as `s^-2`.
+fn unit_factor_end(chars:&[char], from:usize) -> Option<usize> {
+    let mut i = from;
+    while i < chars.len() && chars[i].is_ascii_alphabetic() {
+        i += 1;
+    }
+    let name = chars[from..i].iter().collect::<String>();
+    if Unit::find(&name).is_none() || chars.get(i) == Some(&'(') {
+        return None;
+    }
+    if chars.get(i) == Some(&'^') {
+        let mut j = i + 1;
+        if chars.get(j) == Some(&'-') {
+            j += 1;
+        }
+        let digits = j;
+        while j < chars.len() && chars[j].is_ascii_digit() {
+            j += 1;
+        }
+        if j > digits {
+            i = j;
+        }
+    }
+    Some(i)
+}
+
+/// Parses units such as `kg*m/s^2` into a product of powers of units, the brackets around them optional.
+pub fn parse_units<N:Numeric>(s:&str) -> Result<Term<N>,ParseError>{
+    let (at,chars):(Vec<usize>,Vec<char>) = s.char_indices().unzip();
+    let src = trim(Source { chars: &chars, at: &at, end: s.len() });
+    match (src.chars.first(),src.chars.last()) {
+        (Some('['),Some(']')) if src.len() > 1 => units(src.slice(1..src.len() - 1)),
+        _ => units(src),
+    }
+}
+
+fn units<N:Numeric>(src:Source) -> Result<Term<N>,ParseError>{
+    let chars = src.chars;
+    let unknown = |at:usize| src.error(ParseErrorKind::UnknownUnits(src.text(0..src.len())), at..src.len())
+        .expecting("units such as kg*m/s^2");
+    let mut factors = Vec::new();
+    let mut divide = false;
+    let mut at = 0;
+    loop {
+        let end = unit_factor_end(chars, at).ok_or_else(|| unknown(at))?;
+        let factor = chars[at..end].iter().collect::<String>();
+        let (name,exp) = factor.split_once('^').unwrap_or((&factor,"1"));
+        let exp = N::parse(exp.trim_start_matches('-')).ok_or_else(|| unknown(at))?;
+        let negative = divide != factor.contains("^-");
+        let exp = if negative { -exp } else { exp };
+        factors.push(Term::power(Term::Unit(name.to_string()), Term::Number(exp)));
+        match chars.get(end) {
+            None => break,
+            Some('*') => divide = false,
+            Some('/') => divide = true,
+            Some(_) => return Err(unknown(end)),
+        }
+        at = end + 1;
+    }
+    Ok(Term::product(factors))
+}
+
+/// Splits at every `separator` outside of parentheses and brackets.
+fn split(chars:&[char], separator:char) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut depth = 0;
+    let mut from = 0;
+    for (i,c) in chars.iter().enumerate() {
         match c {
-            'A'..='Z'| 'a'..='z' => match self.mode {
-                ParseMode::None | ParseMode::Literal => {
-                    self.mode = ParseMode::Literal;
-                    self.builder.push(c);
-                    return Ok(());
-                }
-                _ => self.builder_end()?
-            }
-            '0'..='9' => match self.mode {
-                ParseMode::None | ParseMode::Number => {
-                    self.mode = ParseMode::Number;
-                    self.builder.push(c);
-                    return Ok(());
-                }
-                _ => self.builder_end()?
-            }
-            '.' => match self.mode {
-                ParseMode::Number => {
-                    self.builder.push(c);
-                    return Ok(());
-                }
-                _ => self.builder_end()?
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ if *c == separator && depth == 0 => {
+                pieces.push(from..i);
+                from = i + 1;
             }
-            _ => self.builder_end()?
+            _ => {}
         }
+    }
+    pieces.push(from..chars.len());
+    pieces
+}
 
-        match c {
-            '=' => {
-                if let Equation(_,Some(_)) = self.equation {
-                    return Err("Equation already has two sides!".into());
-                }
-                self.last_priority = 0;
-                self.end()?
+/// Splits a line into the equations of a system, `;` inside a matrix literal not counting.
+pub fn statements(s:&str) -> Vec<String> {
+    let chars = s.chars().collect::<Vec<char>>();
+    split(&chars, ';').into_iter().map(|it| chars[it].iter().collect()).collect()
+}
+
+fn trim(src:Source) -> Source {
+    let chars = src.chars;
+    let start = chars.iter().position(|c| *c != ' ').unwrap_or(chars.len());
+    let end = chars.iter().rposition(|c| *c != ' ').map(|it| it + 1).unwrap_or(start);
+    src.slice(start..end)
+}
+
+/// Binding powers of the operators, higher binding tighter, from the loosest:
+/// `or`, `and`, prefix `not`, relations, `+ -`, `* /` and implicit products, `@`, prefix `- +` and `^`.
+/// An infix operator binding less to its left than to its right is left associative, so `a - b - c` is
+/// `(a - b) - c`, and `^` the other way around is right associative, so `2^3^2` is `2^(3^2)`.
+/// Relations chain instead, `0 < x < 1` holding when both of its comparisons do.
+type BindingPower = (u8,u8);
+
+const OR:BindingPower = (1,2);
+const AND:BindingPower = (3,4);
+const NOT:u8 = 5;
+const RELATION:BindingPower = (7,8);
+const SUM:BindingPower = (9,10);
+const PRODUCT:BindingPower = (11,12);
+const MATRIX_PRODUCT:BindingPower = (13,14);
+const SIGN:u8 = 15;
+const POWER:BindingPower = (18,17);
+
+const KEYWORDS:[&str;3] = ["and","or","not"];
+
+/// An operator between two operands.
+enum Infix {
+    Operator(Operator),
+    Relation(Relation),
+    Logic(Logic),
+    /// Adjacent factors such as `2x` or `(a+b)(a-b)`, multiplied.
+    Implicit,
+}
+
+/// Pratt parser over the tokens of the input.
+struct Parser<N> {
+    tokens: Vec<Spanned<N>>,
+    position: usize,
+    /// Byte offset of the end of the input.
+    end: usize,
+}
+
+impl<N:Numeric> Parser<N> {
+    fn peek(&self) -> Option<&Spanned<N>> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Spanned<N>> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Span of the next token, or the end of the input.
+    fn next_span(&self) -> Range<usize> {
+        self.peek().map(|it| it.span.clone()).unwrap_or(self.end..self.end)
+    }
+
+    /// Byte offset after the last token read.
+    fn read_end(&self) -> usize {
+        self.position.checked_sub(1).and_then(|it| self.tokens.get(it)).map(|it| it.span.end).unwrap_or(0)
+    }
+
+    /// Parses operands joined by operators binding at least `min` to their left.
+    fn expression(&mut self, min:u8) -> Result<Term<N>,ParseError>{
+        let mut left = self.operand()?;
+        let mut comparisons:Vec<Term<N>> = Vec::new();
+        while let Some((infix,(left_power,right_power))) = self.infix()? {
+            if left_power < min {
+                break;
             }
-            '+' | '-' | '*' | '/' | '^' => {
-                let oper = Operator::from_char(c).ok_or("Invalid Operator!")?;
-                let priority = oper.priority();
-                if priority <= self.last_priority {
-                    let same = priority == self.last_priority;
-                    let last = self.terms.pop_back().ok_or("No Terms Found!")?;
-                    let last = if same { oper.to_com_term(last) } else { last };
-                    match self.terms.back_mut().ok_or("No Chain / Power Found!")? {
-                        Term::Chain(_, ref mut terms) =>  terms.push_back(last),
-                        Term::Power(base,_) => {
-                            *base = Some(last.into());
-                        }
-                        _ => return Err("Invalid Chain / Power!".into())
+            if !matches!(infix, Infix::Implicit) {
+                self.position += 1;
+            }
+            let right = self.expression(right_power)?;
+            if !matches!(infix, Infix::Relation(_)) {
+                comparisons.clear();
+            }
+            left = match infix {
+                Infix::Operator(operator) => combine(operator, left, right),
+                Infix::Implicit => combine(Operator::Multiply, left, right),
+                Infix::Logic(logic) => match left {
+                    Term::Logic(it, mut terms) if it == logic => {
+                        terms.push(right);
+                        Term::Logic(logic, terms)
                     }
-                    if same {
-                        return Ok(());
+                    left => Term::Logic(logic, vec![left, right]),
+                },
+                // A chain compares each operand with the next.
+                Infix::Relation(relation) => {
+                    let operand = match comparisons.last() {
+                        Some(Term::Compare(_,_,last)) => last.as_ref().clone(),
+                        _ => left,
+                    };
+                    comparisons.push(Term::Compare(relation, operand.into(), right.into()));
+                    match comparisons.as_slice() {
+                        [comparison] => comparison.clone(),
+                        _ => Term::Logic(Logic::And, comparisons.clone()),
                     }
                 }
-                let last = self.terms.pop_back().ok_or("No Terms Found!")?;
-                let term = match oper {
-                    Operator::Power => Term::Power(None,last.into()),
-                    _ => {
-                        let mut deque = VecDeque::new();
-                        deque.push_back(oper.to_com_term(last));
-                        Term::Chain(oper.to_com(), deque)
-                    }
-                };
-                self.terms.push_back(term);
-                self.last_priority = priority;
-            },
+            };
+        }
+        Ok(left)
+    }
+
+    /// The operator after an operand with its binding powers, `None` where the expression ends.
+    fn infix(&self) -> Result<Option<(Infix,BindingPower)>,ParseError>{
+        let Some(next) = self.peek() else { return Ok(None) };
+        let infix = match &next.token {
+            Token::Operator(Operator::Equals) | Token::Close(_) | Token::Comma | Token::Semicolon => return Ok(None),
+            Token::Operator(operator) => (Infix::Operator(*operator), match operator {
+                Operator::Add | Operator::Subtract => SUM,
+                Operator::Multiply | Operator::Divide => PRODUCT,
+                Operator::MatrixProduct => MATRIX_PRODUCT,
+                Operator::Power => POWER,
+                Operator::Equals => unreachable!("`=` ends the expression"),
+            }),
+            Token::Relation(relation) => (Infix::Relation(*relation), RELATION),
+            Token::Name(name) if name == "or" => (Infix::Logic(Logic::Or), OR),
+            Token::Name(name) if name == "and" => (Infix::Logic(Logic::And), AND),
+            _ if self.implicit() => (Infix::Implicit, PRODUCT),
+            Token::Name(name) if Unit::find(name).is_some() =>
+                return Err(ParseError::new(ParseErrorKind::MissingOperator, next.span.clone()).expecting("an operator, or units in brackets such as [m]")),
+            _ => return Err(ParseError::new(ParseErrorKind::MissingOperator, next.span.clone()).expecting("an operator")),
+        };
+        Ok(Some(infix))
+    }
 
-            '(' => {
-                self.paren -= 1;
-                self.last_priority = 999;
+    /// Whether the next token multiplies the one before by adjacency: a factor right after a number,
+    /// or right after a closing parenthesis or bracket. Bracketed units may also follow a number after a space, as in `2 [h]`.
+    fn implicit(&self) -> bool {
+        let (Some(previous),Some(next)) = (self.position.checked_sub(1).and_then(|it| self.tokens.get(it)),self.peek()) else { return false };
+        let starts_factor = match &next.token {
+            Token::Name(name) => !KEYWORDS.contains(&name.as_str()),
+            Token::Open(_) | Token::Units(_) => true,
+            Token::Number(_) => matches!(previous.token, Token::Close(_)),
+            _ => false,
+        };
+        let after_factor = matches!(previous.token, Token::Number(_) | Token::Close(_));
+        let spaced_unit = matches!((&previous.token,&next.token), (Token::Number(_),Token::Units(_)));
+        after_factor && starts_factor && (previous.span.end == next.span.start || spaced_unit)
+    }
+
+    /// Parses a number, name, call, group, matrix or an operand of a prefix operator.
+    fn operand(&mut self) -> Result<Term<N>,ParseError>{
+        let span = self.next_span();
+        let missing = ParseError::new(ParseErrorKind::MissingOperand, span.clone()).expecting(OPERAND);
+        let Some(Spanned { token, .. }) = self.next() else { return Err(missing) };
+        match token {
+            Token::Number(n) => Ok(Term::Number(n)),
+            Token::Units(units) => Ok(units),
+            Token::Name(name) if name == "not" => Ok(Term::Logic(Logic::Not, vec![self.expression(NOT)?])),
+            Token::Name(name) if KEYWORDS.contains(&name.as_str()) => Err(missing),
+            Token::Name(name) => match self.peek().map(|it| &it.token) {
+                Some(Token::Open('(')) => self.call(name, span),
+                _ => Ok(Term::Unknown(name)),
+            },
+            Token::Operator(Operator::Subtract) => Ok(Term::Opposite(self.expression(SIGN)?.into())),
+            Token::Operator(Operator::Add) => self.expression(SIGN),
+            Token::Open('(') => {
+                let term = self.expression(0)?;
+                self.close(span, '(')?;
+                Ok(term)
             }
+            Token::Open(_) => self.matrix(span),
+            _ => Err(missing),
+        }
+    }
 
-            ')' => {
-                self.paren += 1;
-                self.last_priority = 0;
+    /// Reads the parenthesis or bracket closing the one opened at `open`.
+    fn close(&mut self, open:Range<usize>, opening:char) -> Result<(),ParseError>{
+        let (closing,expected) = if opening == '[' { (']',"']'") } else { (')',"')'") };
+        match self.peek() {
+            Some(Spanned { token: Token::Close(c), .. }) if *c == closing => {
+                self.position += 1;
+                Ok(())
             }
-            ' ' => {}
+            Some(Spanned { token: Token::Operator(Operator::Equals), span }) => Err(ParseError::new(ParseErrorKind::NestedEquation, span.clone())),
+            Some(Spanned { span, .. }) => Err(ParseError::new(ParseErrorKind::Unclosed(opening), span.clone()).expecting(expected)),
+            None => Err(ParseError::new(ParseErrorKind::Unclosed(opening), open).expecting(expected)),
+        }
+    }
 
-            _ => {
-                return Err(format!("Invalid Character '{}'",c).into());
+    /// Parses the entries between the opened parenthesis or bracket and the one closing it, separated by `,`
+    /// and for a matrix its rows by `;`.
+    fn entries(&mut self, open:Range<usize>, opening:char) -> Result<Vec<Vec<Term<N>>>,ParseError>{
+        let mut rows = vec![Vec::new()];
+        loop {
+            let entry = self.expression(0)?;
+            rows.last_mut().unwrap().push(entry);
+            match self.peek().map(|it| &it.token) {
+                Some(Token::Comma) => {}
+                Some(Token::Semicolon) if opening == '[' => rows.push(Vec::new()),
+                _ => break,
             }
+            self.position += 1;
+        }
+        self.close(open, opening)?;
+        Ok(rows)
+    }
+
+    fn matrix(&mut self, open:Range<usize>) -> Result<Term<N>,ParseError>{
+        let rows = self.entries(open.clone(), '[')?;
+        matrix::check(&rows).map_err(|err| ParseError::new(ParseErrorKind::InvalidMatrix(err.to_string()), open.start..self.read_end()))?;
+        Ok(Term::Matrix(rows))
+    }
+
+    /// Parses the arguments of a call to `name`, a built-in function or a user-defined one.
+    fn call(&mut self, name:String, start:Range<usize>) -> Result<Term<N>,ParseError>{
+        let open = self.next_span();
+        self.position += 1;
+        let mut args = self.entries(open, '(')?.remove(0);
+        let arity = |expected:&'static str, found:usize| ParseError::new(ParseErrorKind::Arity { name: name.clone(), expected, found }, start.start..self.read_end());
+        if let Some(func) = MatrixFunction::from_name(&name) {
+            return match func.arity() {
+                expected if expected == args.len() => Ok(Term::MatrixFunction(func, args)),
+                1 => Err(arity("1", args.len())),
+                _ => Err(arity("2", args.len())),
+            };
         }
+        Ok(match Function::from_name(&name) {
+            Some(func) if args.len() == 1 => Term::Function(func, args.remove(0).into()),
+            Some(_) => return Err(arity("1", args.len())),
+            None if name == "if" => match <[Term<N>;3]>::try_from(args) {
+                Ok([cond,then,otherwise]) => Term::Piecewise(vec![(cond,then)], Some(otherwise.into())),
+                Err(args) => return Err(arity("3", args.len())),
+            },
+            // `piecewise(cond, value, ..., otherwise)`, the last value optional.
+            None if name == "piecewise" => {
+                if args.len() < 2 {
+                    return Err(arity("at least 2", args.len()));
+                }
+                let otherwise = if args.len() % 2 == 1 { args.pop().map(Box::new) } else { None };
+                let mut args = args.into_iter();
+                let branches = std::iter::from_fn(|| Some((args.next()?, args.next()?))).collect();
+                Term::Piecewise(branches, otherwise)
+            }
+            None => Term::Call(name, args),
+        })
+    }
 
-        return Ok(());
+    /// Checks every token was read, the input ending after at most two sides.
+    fn finish(&self) -> Result<(),ParseError>{
+        let Some(Spanned { token, span }) = self.peek() else { return Ok(()) };
+        let kind = match token {
+            Token::Operator(Operator::Equals) => ParseErrorKind::TooManySides,
+            Token::Close(c) => ParseErrorKind::Unmatched(*c),
+            Token::Semicolon => ParseErrorKind::InvalidCharacter(';'),
+            _ => ParseErrorKind::InvalidCharacter(','),
+        };
+        Err(ParseError::new(kind, span.clone()))
     }
 }
 
-#[derive(PartialEq)]
-enum ParseMode {
-    Number,
-    Literal,
-    None
+/// Joins two operands, appending to a sum, product or matrix product already on the left,
+/// as the operators are left associative.
+fn combine<N:Numeric>(operator:Operator, left:Term<N>, right:Term<N>) -> Term<N> {
+    match (operator,left) {
+        (Operator::Power,left) => Term::Power(Some(left.into()), right.into()),
+        (Operator::MatrixProduct,Term::MatrixProduct(mut factors)) => {
+            factors.push_back(right);
+            Term::MatrixProduct(factors)
+        }
+        (Operator::MatrixProduct,left) => Term::MatrixProduct([left, right].into()),
+        (operator,left) => {
+            let com = operator.to_com();
+            let right = operator.to_com_term(right);
+            match left {
+                Term::Chain(it, mut terms) if it == com => {
+                    terms.push_back(right);
+                    Term::Chain(com, terms)
+                }
+                left => Term::Chain(com, [left, right].into()),
+            }
+        }
+    }
 }
 
-pub fn parse(s:&str) -> Result<Equation,Box<dyn Error>>{
-    let mut chars = s.chars().into_iter().rev();
-    let mut parser = Parser::new();
-    for char in chars {
-        parser.parse(char)?;
+pub fn parse<N:Numeric>(s:&str) -> Result<Equation<N>,ParseError>{
+    let (at,chars):(Vec<usize>,Vec<char>) = s.char_indices().unzip();
+    let src = Source { chars: &chars, at: &at, end: s.len() };
+    let mut parser = Parser { tokens: tokenize(src)?, position: 0, end: s.len() };
+    let left = parser.expression(0)?;
+    let equation = match parser.peek().map(|it| &it.token) {
+        Some(Token::Operator(Operator::Equals)) => {
+            parser.position += 1;
+            Equation(Some(left), Some(parser.expression(0)?))
+        }
+        _ => Equation(None, Some(left)),
+    };
+    parser.finish()?;
+    Ok(equation)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, N};
+    use super::{parse, ParseErrorKind};
+
+    /// The value of the formula `s` at `x = 2`, printed.
+    fn value(s:&str) -> String {
+        testing::value::<N>(s, &[("x",2)]).unwrap()
     }
-    parser.end()?;
-    return Ok(parser.equation);
-}
\ No newline at end of file
+
+    /// The kind of the error parsing `s`, its span and its hint.
+    fn error(s:&str) -> (ParseErrorKind, std::ops::Range<usize>, Option<&'static str>) {
+        let err = parse::<N>(s).err().unwrap();
+        (err.kind, err.span, err.expected)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("2^3^2"), "512");
+        assert_eq!(value("-x^2"), "-4");
+        assert_eq!(value("x^-2"), "0.25");
+        assert_eq!(value("2*-3"), "-6");
+        assert_eq!(value("1 + 2*3"), "7");
+        assert_eq!(value("-2^2 + 3"), "-1");
+        assert_eq!(value("2x^2"), "8");
+    }
+
+    #[test]
+    fn associativity() {
+        assert_eq!(value("1 - 2 - 3"), "-4");
+        assert_eq!(value("8 / 4 / 2"), "1");
+        assert_eq!(value("2^-1^2"), "0.5");
+    }
+
+    #[test]
+    fn error_spans() {
+        assert_eq!(error("1 + "), (ParseErrorKind::MissingOperand, 4..4, Some("a number, name or '('")));
+        assert_eq!(error("2 $ 3"), (ParseErrorKind::InvalidCharacter('$'), 2..3, Some("an operator or operand")));
+        assert_eq!(error("(1 + 2"), (ParseErrorKind::Unclosed('('), 0..1, Some("')'")));
+        assert_eq!(error("1 + 2)"), (ParseErrorKind::Unmatched(')'), 5..6, None));
+        assert_eq!(error("x y"), (ParseErrorKind::MissingOperator, 2..3, Some("an operator")));
+        assert_eq!(error("012"), (ParseErrorKind::LeadingZero("012".to_string()), 0..3, None));
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(parse::<N>("3 [m]").unwrap().to_string(), " = 3*[m]");
+        assert_eq!(parse::<N>("3[m]").unwrap().to_string(), " = 3*[m]");
+        assert_eq!(parse::<N>("9.81 [m/s^2]").unwrap().to_string(), " = 9.81*[m] / [s]^2");
+        assert_eq!(parse::<N>("2t").unwrap().to_string(), " = 2*t");
+        assert_eq!(error("2 t"), (ParseErrorKind::MissingOperator, 2..3, Some("an operator, or units in brackets such as [m]")));
+        assert_eq!(error("2 x"), (ParseErrorKind::MissingOperator, 2..3, Some("an operator")));
+        assert_eq!(error("[x]"), (ParseErrorKind::UnknownUnits("x".to_string()), 1..2, Some("units such as kg*m/s^2")));
+        assert_eq!(parse::<N>("[(x)]").unwrap().to_string(), " = [x]");
+        assert_eq!(parse::<N>("[x, y]").unwrap().to_string(), " = [x, y]");
+    }
+
+    #[test]
+    fn pointer() {
+        let input = "(1 + 2";
+        assert_eq!(parse::<N>(input).err().unwrap().pointer(input), "^");
+        let input = "sin(x) + * y";
+        assert_eq!(parse::<N>(input).err().unwrap().pointer(input), "         ^");
+    }
+}
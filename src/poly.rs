@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use crate::eval::EvalError;
+use crate::numeric::{Numeric, Ordered};
+
+/// Sum of numbers, failing where the number type can not hold it.
+pub(crate) fn add<N:Numeric>(a:&N, b:&N) -> Result<N,EvalError> {
+    a.checked_add(b).ok_or_else(|| EvalError::Overflow(format!("{} + {}",a,b)))
+}
+
+pub(crate) fn sub<N:Numeric>(a:&N, b:&N) -> Result<N,EvalError> {
+    a.checked_sub(b).ok_or_else(|| EvalError::Overflow(format!("{} - {}",a,b)))
+}
+
+pub(crate) fn mul<N:Numeric>(a:&N, b:&N) -> Result<N,EvalError> {
+    a.checked_mul(b).ok_or_else(|| EvalError::Overflow(format!("{} * {}",a,b)))
+}
+
+/// Quotient of numbers known not to divide by zero, so `None` can only mean out of range.
+pub(crate) fn div<N:Numeric>(a:&N, b:&N) -> Result<N,EvalError> {
+    a.checked_div(b).ok_or_else(|| EvalError::Overflow(format!("{} / {}",a,b)))
+}
+
+/// Dense polynomial in one unknown, `coefs[i]` being the coefficient of `x^i`.
+#[derive(PartialEq,Eq,Clone)]
+pub struct Polynomial<N> {
+    coefs:Vec<N>,
+}
+
+impl<N:Numeric> Polynomial<N> {
+    pub fn new(mut coefs:Vec<N>) -> Self {
+        while coefs.last().is_some_and(|it| it.is_zero()) {
+            coefs.pop();
+        }
+        Self { coefs }
+    }
+
+    /// Builds a polynomial from an exponent -> coefficient map.
+    /// Negative exponents are cleared by multiplying with the lowest power of the unknown.
+    pub fn from_map(map:&BTreeMap<Ordered<N>,N>) -> Result<Self,EvalError> {
+        let shift = map.keys().next().filter(|it| it.0.is_negative()).map(|it| -it.0.clone()).unwrap_or_default();
+        let mut coefs = Vec::new();
+        for (exp,coef) in map {
+            let exp = exp.0.clone() + &shift;
+            if !exp.is_integer() {
+                return Err(EvalError::FractionalExponent((exp - shift).to_string()));
+            }
+            let index = exp.to_usize().ok_or_else(|| EvalError::Overflow(format!("Exponent {}",exp)))?;
+            if coefs.len() <= index {
+                coefs.resize(index + 1, N::from(0));
+            }
+            coefs[index] = coef.clone();
+        }
+        Ok(Self::new(coefs))
+    }
+
+    pub fn coefs(&self) -> &[N] {
+        &self.coefs
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefs.len().saturating_sub(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefs.is_empty()
+    }
+
+    /// Value at `x` by Horner's rule, an error when it is out of range for the number type.
+    pub fn eval(&self, x:&N) -> Result<N,EvalError> {
+        self.coefs.iter().rev().try_fold(N::from(0), |acc,coef| add(&mul(&acc, x)?, coef))
+    }
+
+    pub fn derivative(&self) -> Result<Self,EvalError> {
+        Ok(Self::new(self.coefs.iter().enumerate().skip(1).map(|(exp,coef)| mul(coef, &N::from(exp as i32))).collect::<Result<_,_>>()?))
+    }
+
+    /// Roots each repeated by its multiplicity, the complex ones too when the number type has them.
+    pub fn roots(&self) -> Result<Vec<N>,EvalError> {
+        N::polynomial_roots(self)
+    }
+
+    /// Real roots in ascending order, each repeated by its multiplicity.
+    pub fn real_roots(&self) -> Result<Vec<N>,EvalError> {
+        let mut roots = Vec::new();
+        for (root,multiplicity) in self.distinct_roots()? {
+            roots.extend(std::iter::repeat_n(root, multiplicity));
+        }
+        Ok(roots)
+    }
+
+    /// Quotient by `x - root`, the remainder dropped.
+    pub fn deflate(&self, root:&N) -> Result<Self,EvalError> {
+        let mut quotient = vec![N::from(0); self.degree()];
+        let mut carry = N::from(0);
+        for (i,coef) in self.coefs.iter().enumerate().skip(1).rev() {
+            carry = add(&mul(&carry, root)?, coef)?;
+            quotient[i - 1] = carry.clone();
+        }
+        Ok(Self::new(quotient))
+    }
+
+    fn distinct_roots(&self) -> Result<Vec<(N,usize)>,EvalError> {
+        if self.is_zero() {
+            return Err(EvalError::InfiniteSolutions);
+        }
+        let zeros = self.coefs.iter().take_while(|it| it.is_zero()).count();
+        let reduced = Self::new(self.coefs[zeros..].to_vec());
+        let mut roots = match reduced.degree() {
+            0 => Vec::new(),
+            1 => vec![(div(&-reduced.coefs[0].clone(), &reduced.coefs[1])?,1)],
+            2 => reduced.quadratic_roots()?,
+            _ => reduced.numeric_roots()?,
+        };
+        if zeros > 0 {
+            roots.push((N::from(0),zeros));
+            roots.sort_by(|a,b| a.0.total_cmp(&b.0));
+        }
+        Ok(roots)
+    }
+
+    fn quadratic_roots(&self) -> Result<Vec<(N,usize)>,EvalError> {
+        let (a,b,c) = (&self.coefs[2],&self.coefs[1],&self.coefs[0]);
+        let disc = sub(&mul(b, b)?, &mul(&mul(&N::from(4), a)?, c)?)?;
+        let twice = mul(&N::from(2), a)?;
+        if disc.is_negative() {
+            return Ok(Vec::new());
+        }
+        if disc.is_zero() {
+            return Ok(vec![(div(&-b.clone(), &twice)?,2)]);
+        }
+        let sqrt = disc.sqrt().ok_or_else(|| EvalError::Undefined(format!("sqrt({})",disc)))?;
+        let mut roots = vec![
+            (self.snap(div(&sub(&-b.clone(), &sqrt)?, &twice)?),1),
+            (self.snap(div(&add(&-b.clone(), &sqrt)?, &twice)?),1),
+        ];
+        roots.sort_by(|a,b| a.0.total_cmp(&b.0));
+        Ok(roots)
+    }
+
+    /// Fujiwara's bound on the size of the roots, `2 max |a_(n-k) / a_n|^(1/k)` plus one for rounding,
+    /// far tighter than `1 + max |a_k / a_n|` when the coefficients are large, so the search stays in range.
+    fn root_bound(&self) -> Result<N,EvalError> {
+        let lead = &self.coefs[self.degree()];
+        let mut bound = N::from(0);
+        for (k,coef) in self.coefs.iter().rev().enumerate().skip(1).filter(|(_,coef)| !coef.is_zero()) {
+            let ratio = div(coef, lead)?.abs();
+            let root = ratio.pow(&(N::from(1) / N::from(k as i32))).ok_or_else(|| EvalError::Undefined(format!("{}^(1/{})",ratio,k)))?;
+            if root > bound {
+                bound = root;
+            }
+        }
+        add(&N::from(1), &mul(&N::from(2), &bound)?)
+    }
+
+    /// Locates the roots between consecutive critical points, where the polynomial is monotonic.
+    /// A critical point is itself a root when the polynomial vanishes there, one degree more repeated than in the derivative.
+    fn numeric_roots(&self) -> Result<Vec<(N,usize)>,EvalError> {
+        let lead = &self.coefs[self.degree()];
+        let bound = self.root_bound()?;
+        let sign_high = lead.signum();
+        let sign_low = if self.degree().is_multiple_of(2) { sign_high.clone() } else { -sign_high.clone() };
+
+        let mut points = vec![(-bound.clone(),sign_low,false)];
+        let mut roots = Vec::new();
+        for (critical,multiplicity) in self.derivative()?.distinct_roots()? {
+            let value = self.eval(&critical)?;
+            let is_root = self.vanishes(&critical, &value)?;
+            if is_root {
+                roots.push((critical.clone(),multiplicity + 1));
+            }
+            points.push((critical,value.signum(),is_root));
+        }
+        points.push((bound,sign_high,false));
+
+        for window in points.windows(2) {
+            let [(low,sign_low,root_low),(high,sign_high,root_high)] = window else { unreachable!() };
+            if *root_low || *root_high || sign_low == sign_high {
+                continue;
+            }
+            roots.push((self.bisect(low.clone(), high.clone(), sign_low)?,1));
+        }
+        roots.sort_by(|a,b| a.0.total_cmp(&b.0));
+        Ok(roots)
+    }
+
+    fn vanishes(&self, x:&N, value:&N) -> Result<bool,EvalError> {
+        let mut scale = N::from(0);
+        let mut power = N::from(1);
+        for (exp,coef) in self.coefs.iter().enumerate() {
+            if exp > 0 {
+                power = mul(&power, &x.abs())?;
+            }
+            scale = add(&scale, &mul(coef, &power)?.abs())?;
+        }
+        Ok(value.abs() <= scale / N::from(1_000_000) / N::from(1_000_000))
+    }
+
+    fn bisect(&self, mut low:N, mut high:N, sign_low:&N) -> Result<N,EvalError> {
+        loop {
+            let mid = (low.clone() + &high) / N::from(2);
+            if high.clone() - &low <= N::resolution() || mid == low || mid == high {
+                return Ok(self.snap(mid));
+            }
+            let value = self.eval(&mid)?;
+            if value.is_zero() {
+                return Ok(self.snap(mid));
+            }
+            if value.signum() == *sign_low { low = mid } else { high = mid }
+        }
+    }
+
+    /// Prefers the shortest decimal that is an exact root, so `2` is not printed as `1.99999…`.
+    fn snap(&self, root:N) -> N {
+        for dp in 0..=12 {
+            let rounded = root.round_dp(dp);
+            if self.eval(&rounded).is_ok_and(|it| it.is_zero()) {
+                return rounded;
+            }
+        }
+        root.round_working()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use crate::eval::EvalError;
+    use super::Polynomial;
+
+    /// Real roots of the polynomial with coefficients `coefs`, constant first, with 28 digit decimals.
+    fn roots(coefs:&[i128]) -> Result<Vec<String>,EvalError> {
+        let poly = Polynomial::new(coefs.iter().map(|it| Decimal::from_i128_with_scale(*it, 0)).collect());
+        Ok(poly.real_roots()?.iter().map(|it| it.round_dp(10).to_string()).collect())
+    }
+
+    #[test]
+    fn large_coefficients() {
+        assert_eq!(roots(&[-10_000_000_000, 0, 0, 0, 1]).unwrap(), ["-316.2277660168", "316.2277660168"]);
+        assert_eq!(roots(&[100_000_000_000, 0, 0, 1]).unwrap(), ["-4641.5888336128"]);
+        assert_eq!(roots(&[100_000_000, -1, 0, 0, 0, 1]).unwrap(), ["-39.8107202251"]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(matches!(roots(&[1, 100_000_000_000_000_000_000, 1]), Err(EvalError::Overflow(_))));
+        assert!(matches!(roots(&[1, 0, 0, 100_000_000_000_000_000_000, 0, 1]), Err(EvalError::Overflow(_))));
+    }
+}
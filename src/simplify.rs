@@ -0,0 +1,330 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use crate::numeric::Numeric;
+use crate::term::{Commutative, Function, MatrixFunction, Term};
+
+impl<N:Numeric> Term<N> {
+    /// Canonical form of the term: nested chains are flattened, like terms and like powers merged,
+    /// and the identities `x*0`, `x*1`, `x+0`, `x^1`, `x^0`, `x/x`, `ln(e)` applied.
+    /// `Opposite` and `MulInverse` are rewritten as `-1 * x` and `x^-1`, so equivalent inputs print the same way.
+    pub fn simplify(&self) -> Term<N> {
+        match self {
+            Term::Number(_) => self.clone(),
+            Term::Unknown(_) | Term::Unit(_) | Term::Power(None,_) => self.clone(),
+            Term::Opposite(b) => Self::product(vec![Term::Number(N::from(-1)), b.simplify()]),
+            Term::MulInverse(b) => Self::power(b.simplify(), Term::Number(N::from(-1))),
+            Term::Power(Some(base),exp) => Self::power(base.simplify(), exp.simplify()),
+            Term::Function(func,b) => Self::function(*func, b.simplify()),
+            Term::Call(name,args) => Term::Call(name.clone(), args.iter().map(Term::simplify).collect()),
+            Term::MatrixFunction(func,args) => Term::MatrixFunction(*func, args.iter().map(Term::simplify).collect()),
+            Term::Chain(Commutative::Add,terms) => Self::sum(terms.iter().map(Term::simplify).collect()),
+            Term::Chain(Commutative::Multiply,terms) => Self::product(terms.iter().map(Term::simplify).collect()),
+            Term::Matrix(rows) => Term::Matrix(rows.iter().map(|row| row.iter().map(Term::simplify).collect()).collect()),
+            Term::MatrixProduct(factors) => Self::matrix_product(factors.iter().map(Term::simplify).collect()),
+            Term::Compare(relation,left,right) => Self::relation(*relation, left.simplify(), right.simplify()),
+            Term::Logic(logic,terms) => Self::logic(*logic, terms.iter().map(Term::simplify).collect()),
+            Term::Piecewise(branches,otherwise) => Self::piecewise(
+                branches.iter().map(|(cond,value)| (cond.simplify(), value.simplify())).collect(),
+                otherwise.as_deref().map(Term::simplify),
+            ),
+        }
+    }
+
+    fn chain(com:Commutative, mut terms:Vec<Term<N>>, identity:N) -> Term<N> {
+        match terms.len() {
+            0 => Term::Number(identity),
+            1 => terms.pop().unwrap(),
+            _ => Term::Chain(com, terms.into()),
+        }
+    }
+
+    fn flatten(com:&Commutative, terms:Vec<Term<N>>, into:&mut Vec<Term<N>>) {
+        for term in terms {
+            match term {
+                Term::Chain(inner,children) if inner == *com => Self::flatten(com, children.into(), into),
+                _ => into.push(term),
+            }
+        }
+    }
+
+    /// Splits the numeric coefficient off a simplified term, `2*x*y` into `2` and `x*y`.
+    fn split_coefficient(term:Term<N>) -> (N,Term<N>) {
+        match term {
+            Term::Chain(Commutative::Multiply,mut factors) if matches!(factors.front(),Some(Term::Number(_))) => {
+                let Some(Term::Number(coef)) = factors.pop_front() else { unreachable!() };
+                (coef, Self::chain(Commutative::Multiply, factors.into(), N::from(1)))
+            }
+            _ => (N::from(1), term),
+        }
+    }
+
+    /// Sums already simplified terms.
+    pub(crate) fn sum(terms:Vec<Term<N>>) -> Term<N> {
+        let mut flat = Vec::new();
+        Self::flatten(&Commutative::Add, terms, &mut flat);
+        let mut constant = N::from(0);
+        let mut groups:Vec<(Term<N>,N)> = Vec::new();
+        // Numbers whose sum is out of range are left apart, for evaluation to report.
+        let mut unfolded = Vec::new();
+        for term in flat {
+            if let Term::Number(n) = term {
+                match constant.checked_add(&n) {
+                    Some(sum) => constant = sum,
+                    None => unfolded.push(Term::Number(n)),
+                }
+                continue;
+            }
+            let (coef,rest) = Self::split_coefficient(term);
+            match groups.iter_mut().find(|(it,_)| *it == rest).map(|(_,sum)| (sum.checked_add(&coef),sum)) {
+                Some((Some(total),sum)) => *sum = total,
+                _ => groups.push((rest,coef)),
+            }
+        }
+        groups.retain(|(_,coef)| !coef.is_zero());
+        groups.sort_by(|(a,_),(b,_)| Self::degree(b).total_cmp(&Self::degree(a)).then_with(|| Self::compare_summands(a, b)));
+        let mut result = groups.into_iter()
+            .map(|(rest,coef)| if coef == N::from(1) { rest } else { Self::product(vec![Term::Number(coef), rest]) })
+            .collect::<Vec<Term<N>>>();
+        if !constant.is_zero() {
+            result.push(Term::Number(constant));
+        }
+        result.extend(unfolded);
+        Self::chain(Commutative::Add, result, N::from(0))
+    }
+
+    /// Multiplies already simplified terms, merging factors of the same base by adding their exponents.
+    /// Matrices are multiplied entry by entry, so they are left apart rather than read as a matrix power.
+    pub(crate) fn product(terms:Vec<Term<N>>) -> Term<N> {
+        let mut flat = Vec::new();
+        Self::flatten(&Commutative::Multiply, terms, &mut flat);
+        let mut coef = N::from(1);
+        let mut groups:Vec<(Term<N>,Term<N>)> = Vec::new();
+        // Numbers whose product is out of range are left apart, for evaluation to report.
+        let mut unfolded = Vec::new();
+        for term in flat {
+            let (base,exp) = match term {
+                Term::Number(n) => {
+                    match coef.checked_mul(&n) {
+                        Some(product) => coef = product,
+                        None => unfolded.push(Term::Number(n)),
+                    }
+                    continue;
+                }
+                Term::Power(Some(base),exp) => (*base,*exp),
+                _ => (term,Term::Number(N::from(1))),
+            };
+            match groups.iter_mut().find(|(it,_)| *it == base && !Self::is_matrix(it)) {
+                Some((_,sum)) => *sum = Self::sum(vec![sum.clone(), exp]),
+                None => groups.push((base,exp)),
+            }
+        }
+        if coef.is_zero() {
+            return Term::Number(N::from(0));
+        }
+        let mut factors = Vec::new();
+        for (base,exp) in groups {
+            match Self::power(base, exp) {
+                Term::Number(n) => match coef.checked_mul(&n) {
+                    Some(product) => coef = product,
+                    None => unfolded.push(Term::Number(n)),
+                },
+                factor => factors.push(factor),
+            }
+        }
+        factors.extend(unfolded);
+        if let [Term::Chain(Commutative::Add,terms)] = factors.as_slice() {
+            // A lone sum takes the coefficient term by term, so `-(x - 3)` merges with its neighbours.
+            if coef != N::from(1) {
+                return Self::sum(terms.iter().map(|it| Self::product(vec![Term::Number(coef.clone()), it.clone()])).collect());
+            }
+        }
+        factors.sort_by(|a,b| Self::compare(Self::base(a), Self::base(b)).then_with(|| Self::compare(a, b)));
+        if coef != N::from(1) || factors.is_empty() {
+            factors.insert(0, Term::Number(coef));
+        }
+        Self::chain(Commutative::Multiply, factors, N::from(1))
+    }
+
+    /// Multiplies already simplified matrices in order, nested products flattened.
+    fn matrix_product(factors:Vec<Term<N>>) -> Term<N> {
+        let mut flat = VecDeque::new();
+        for factor in factors {
+            match factor {
+                Term::MatrixProduct(inner) => flat.extend(inner),
+                _ => flat.push_back(factor),
+            }
+        }
+        match flat.len() {
+            1 => flat.pop_back().unwrap(),
+            _ => Term::MatrixProduct(flat),
+        }
+    }
+
+    /// Raises an already simplified base to an already simplified exponent.
+    pub(crate) fn power(base:Term<N>, exp:Term<N>) -> Term<N> {
+        match (&base,&exp) {
+            (_,Term::Number(e)) if e.is_zero() => Term::Number(N::from(1)),
+            (_,Term::Number(e)) if *e == N::from(1) => base,
+            (Term::Number(b),_) if *b == N::from(1) => base,
+            (Term::Number(b),Term::Number(e)) => match b.pow(e) {
+                Some(n) => Term::Number(n),
+                None => Term::Power(Some(base.into()), exp.into()),
+            },
+            (Term::Power(Some(inner),inner_exp),Term::Number(e)) if e.is_integer() =>
+                Self::power(*inner.clone(), Self::product(vec![*inner_exp.clone(), exp])),
+            (Term::Chain(Commutative::Multiply,factors),Term::Number(e)) if e.is_integer() =>
+                Self::product(factors.iter().map(|it| Self::power(it.clone(), exp.clone())).collect()),
+            _ => Term::Power(Some(base.into()), exp.into()),
+        }
+    }
+
+    /// Applies a function to an already simplified argument, folding numbers where it is defined.
+    pub(crate) fn function(func:Function, arg:Term<N>) -> Term<N> {
+        match &arg {
+            // `e` is left symbolic, but its logarithm is known exactly, so `e^x` differentiates and integrates without `ln(e)`.
+            Term::Unknown(name) if func == Function::Ln && name == "e" => Term::Number(N::from(1)),
+            Term::Number(n) => match func.apply(n) {
+                Some(value) => Term::Number(value),
+                None => Term::Function(func, arg.into()),
+            },
+            _ => Term::Function(func, arg.into()),
+        }
+    }
+
+    /// Whether the term stands for a matrix rather than a number.
+    fn is_matrix(term:&Term<N>) -> bool {
+        match term {
+            Term::Matrix(_) | Term::MatrixProduct(_) => true,
+            Term::MatrixFunction(func,_) => *func != MatrixFunction::Det,
+            Term::Power(Some(base),_) => Self::is_matrix(base),
+            _ => false,
+        }
+    }
+
+    fn base(term:&Term<N>) -> &Term<N> {
+        match term {
+            Term::Power(Some(base),_) => base,
+            _ => term,
+        }
+    }
+
+    /// Total degree in the unknowns, used to order sums from the highest power down.
+    fn degree(term:&Term<N>) -> N {
+        match term {
+            Term::Unknown(_) => N::from(1),
+            Term::Power(Some(base),exp) => match exp.as_ref() {
+                Term::Number(e) => Self::degree(base) * e,
+                _ => N::from(0),
+            },
+            Term::Chain(Commutative::Multiply,factors) => factors.iter().fold(N::from(0), |acc,it| acc + Self::degree(it)),
+            _ => N::from(0),
+        }
+    }
+
+    /// Order of summands of the same degree, their coefficients split off: factor by factor on the bases,
+    /// so unknowns come in alphabetical order and `x^2*y` before `y^3`, the higher power of a base first.
+    fn compare_summands<'a>(a:&'a Term<N>, b:&'a Term<N>) -> Ordering {
+        let factors = |term:&'a Term<N>| -> Vec<&'a Term<N>> { match term {
+            Term::Chain(Commutative::Multiply,factors) => factors.iter().collect(),
+            _ => vec![term],
+        }};
+        let exponent = |term:&Term<N>| match term {
+            Term::Power(Some(_),exp) => exp.as_ref().clone(),
+            _ => Term::Number(N::from(1)),
+        };
+        let (factors_a,factors_b) = (factors(a),factors(b));
+        factors_a.iter().zip(&factors_b)
+            .map(|(x,y)| Self::compare(Self::base(x), Self::base(y)).then_with(|| Self::compare(&exponent(y), &exponent(x))))
+            .find(|it| it.is_ne())
+            .unwrap_or_else(|| factors_a.len().cmp(&factors_b.len()))
+            .then_with(|| Self::compare(a, b))
+    }
+
+    fn rank(term:&Term<N>) -> u8 {
+        match term {
+            Term::Number(_) => 0,
+            Term::Unknown(_) => 1,
+            Term::Power(..) => 2,
+            Term::Chain(Commutative::Multiply,_) => 3,
+            Term::Chain(Commutative::Add,_) => 4,
+            Term::Function(..) => 5,
+            Term::Call(..) => 6,
+            Term::Opposite(_) => 7,
+            Term::MulInverse(_) => 8,
+            Term::Unit(_) => 9,
+            Term::Matrix(_) => 10,
+            Term::MatrixProduct(_) => 11,
+            Term::Compare(..) => 12,
+            Term::Logic(..) => 13,
+            Term::Piecewise(..) => 14,
+            Term::MatrixFunction(..) => 15,
+        }
+    }
+
+    /// Total structural order on terms, deciding where each term sits in a canonical chain.
+    fn compare(a:&Term<N>, b:&Term<N>) -> Ordering {
+        match (a,b) {
+            (Term::Number(x),Term::Number(y)) => x.total_cmp(y),
+            (Term::Unknown(x),Term::Unknown(y)) | (Term::Unit(x),Term::Unit(y)) => x.cmp(y),
+            (Term::Power(base_a,exp_a),Term::Power(base_b,exp_b)) => match (base_a,base_b) {
+                (Some(base_a),Some(base_b)) => Self::compare(base_a, base_b),
+                _ => base_a.is_some().cmp(&base_b.is_some()),
+            }.then_with(|| Self::compare(exp_a, exp_b)),
+            (Term::Chain(com_a,terms_a),Term::Chain(com_b,terms_b)) if com_a == com_b => terms_a.iter().zip(terms_b.iter())
+                .map(|(x,y)| Self::compare(x, y))
+                .find(|it| it.is_ne())
+                .unwrap_or_else(|| terms_a.len().cmp(&terms_b.len())),
+            (Term::Function(func_a,x),Term::Function(func_b,y)) => func_a.cmp(func_b).then_with(|| Self::compare(x, y)),
+            (Term::Call(name_a,args_a),Term::Call(name_b,args_b)) => name_a.cmp(name_b).then_with(|| args_a.iter().zip(args_b.iter())
+                .map(|(x,y)| Self::compare(x, y))
+                .find(|it| it.is_ne())
+                .unwrap_or_else(|| args_a.len().cmp(&args_b.len()))),
+            (Term::MatrixFunction(func_a,args_a),Term::MatrixFunction(func_b,args_b)) => func_a.cmp(func_b).then_with(|| args_a.iter().zip(args_b.iter())
+                .map(|(x,y)| Self::compare(x, y))
+                .find(|it| it.is_ne())
+                .unwrap_or_else(|| args_a.len().cmp(&args_b.len()))),
+            (Term::Matrix(rows_a),Term::Matrix(rows_b)) => rows_a.iter().flatten().zip(rows_b.iter().flatten())
+                .map(|(x,y)| Self::compare(x, y))
+                .find(|it| it.is_ne())
+                .unwrap_or_else(|| (rows_a.len(),rows_a[0].len()).cmp(&(rows_b.len(),rows_b[0].len()))),
+            (Term::MatrixProduct(terms_a),Term::MatrixProduct(terms_b)) => terms_a.iter().zip(terms_b.iter())
+                .map(|(x,y)| Self::compare(x, y))
+                .find(|it| it.is_ne())
+                .unwrap_or_else(|| terms_a.len().cmp(&terms_b.len())),
+            (Term::Compare(relation_a,left_a,right_a),Term::Compare(relation_b,left_b,right_b)) => relation_a.cmp(relation_b)
+                .then_with(|| Self::compare(left_a, left_b))
+                .then_with(|| Self::compare(right_a, right_b)),
+            (Term::Logic(logic_a,terms_a),Term::Logic(logic_b,terms_b)) => logic_a.cmp(logic_b).then_with(|| terms_a.iter().zip(terms_b.iter())
+                .map(|(x,y)| Self::compare(x, y))
+                .find(|it| it.is_ne())
+                .unwrap_or_else(|| terms_a.len().cmp(&terms_b.len()))),
+            (Term::Opposite(x),Term::Opposite(y)) | (Term::MulInverse(x),Term::MulInverse(y)) => Self::compare(x, y),
+            _ => Self::rank(a).cmp(&Self::rank(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{formula, N};
+
+    fn simplify(s:&str) -> String {
+        formula::<N>(s).simplify().to_string()
+    }
+
+    #[test]
+    fn collects() {
+        assert_eq!(simplify("x + x + 1 + 2"), "2*x + 3");
+        assert_eq!(simplify("x*x*3*x"), "3*x^3");
+        assert_eq!(simplify("x - x"), "0");
+        assert_eq!(simplify("2*(x*y) + y*x"), "3*x*y");
+    }
+
+    #[test]
+    fn orders_summands() {
+        assert_eq!(simplify("y + 2*x"), "2*x + y");
+        assert_eq!(simplify("-y^3 + x^2*y"), "x^2*y - y^3");
+        assert_eq!(simplify("x*y + 3*x^2 + 1"), "3*x^2 + x*y + 1");
+        assert_eq!(simplify("b + 5*a - c"), "5*a + b - c");
+    }
+}
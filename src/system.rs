@@ -0,0 +1,344 @@
+use std::collections::BTreeMap;
+use crate::console::ConsoleManager;
+use crate::constant::Constant;
+use crate::eval::{EvalError, Evaluator};
+use crate::matrix::{self, MatrixError, Rows};
+use crate::numeric::Numeric;
+use crate::poly::{add, div, mul, sub};
+use crate::term::{Commutative, Term};
+
+/// `constant + Σ coefs[name] * name`
+#[derive(Clone)]
+pub struct Linear<N> {
+    coefs:BTreeMap<String,N>,
+    constant:N,
+}
+
+impl<N:Numeric> Linear<N> {
+    fn constant(constant:N) -> Self {
+        Self { coefs: BTreeMap::new(), constant }
+    }
+
+    fn unknown(name:&str) -> Self {
+        Self { coefs: [(name.to_string(),N::from(1))].into(), constant: N::from(0) }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.coefs.is_empty()
+    }
+
+    fn add(mut self, other:&Linear<N>) -> Result<Self,EvalError> {
+        for (name,coef) in &other.coefs {
+            let sum = self.coefs.entry(name.clone()).or_insert(N::from(0));
+            *sum = add(sum, coef)?;
+        }
+        self.constant = add(&self.constant, &other.constant)?;
+        self.coefs.retain(|_,coef| !coef.is_zero());
+        Ok(self)
+    }
+
+    fn scale(mut self, factor:N) -> Result<Self,EvalError> {
+        for coef in self.coefs.values_mut() {
+            *coef = mul(coef, &factor)?;
+        }
+        self.constant = mul(&self.constant, &factor)?;
+        self.coefs.retain(|_,coef| !coef.is_zero());
+        Ok(self)
+    }
+
+    /// Reads `term` as a linear combination of its unknowns.
+    pub fn from_term(term:&Term<N>) -> Result<Self,EvalError> {
+        let linear = match term {
+            Term::Number(n) => Self::constant(n.clone()),
+            Term::Unknown(name) => match Constant::find(name) {
+                Some(constant) => Self::constant(constant.value()?),
+                None => Self::unknown(name),
+            },
+            Term::Opposite(b) => Self::from_term(b)?.scale(N::from(-1))?,
+            Term::MulInverse(b) => {
+                let b = Self::from_term(b)?;
+                if !b.is_constant() {
+                    return Err(EvalError::NotLinear(term.to_string()));
+                }
+                Self::constant(N::from(1).checked_div(&b.constant).ok_or(EvalError::DivisionByZero)?)
+            }
+            Term::Power(Some(base),exp) => {
+                let base = Self::from_term(base)?;
+                let exp = Self::from_term(exp)?;
+                match (base.is_constant(),exp.is_constant()) {
+                    (true,true) => Self::constant(base.constant.pow(&exp.constant).ok_or_else(|| EvalError::Undefined(term.to_string()))?),
+                    (false,true) if exp.constant == N::from(1) => base,
+                    (false,true) if exp.constant.is_zero() => Self::constant(N::from(1)),
+                    _ => return Err(EvalError::NotLinear(term.to_string())),
+                }
+            }
+            Term::Chain(Commutative::Add,terms) => {
+                let mut result = Self::constant(N::from(0));
+                for term in terms {
+                    result = result.add(&Self::from_term(term)?)?;
+                }
+                result
+            }
+            Term::Chain(Commutative::Multiply,terms) => {
+                let mut result = Self::constant(N::from(1));
+                for factor in terms {
+                    let factor = Self::from_term(factor)?;
+                    result = match (result.is_constant(),factor.is_constant()) {
+                        (_,true) => result.scale(factor.constant)?,
+                        (true,false) => factor.scale(result.constant)?,
+                        _ => return Err(EvalError::NotLinear(term.to_string())),
+                    };
+                }
+                result
+            }
+            Term::Function(func,b) => {
+                let b = Self::from_term(b)?;
+                if !b.is_constant() {
+                    return Err(EvalError::NotLinear(term.to_string()));
+                }
+                Self::constant(func.apply(&b.constant).ok_or_else(|| EvalError::Undefined(format!("{}({})",func,b.constant)))?)
+            }
+            Term::Call(name,_) => return Err(EvalError::UndefinedFunction(name.clone())),
+            Term::Unit(name) => return Err(EvalError::Unsolvable(format!("Can not solve with units, found {}",name))),
+            Term::Matrix(_) | Term::MatrixProduct(_) | Term::MatrixFunction(..) => return Err(EvalError::Unsolvable("Can not solve with matrices".to_string())),
+            Term::Compare(..) | Term::Logic(..) | Term::Piecewise(..) => return Err(EvalError::Unsolvable(format!("Can not solve with conditions: {}",term))),
+            Term::Power(None,_) => return Err(EvalError::MissingBase),
+        };
+        Ok(linear)
+    }
+
+    fn to_term(&self) -> Term<N> {
+        let mut terms = self.coefs.iter()
+            .map(|(name,coef)| Term::product(vec![Term::Number(coef.clone()), Term::Unknown(name.clone())]))
+            .collect::<Vec<Term<N>>>();
+        terms.push(Term::Number(self.constant.clone()));
+        // Led by a term that is not subtracted where there is one, reading `3 - y` rather than `-y + 3`.
+        match Term::sum(terms) {
+            Term::Chain(Commutative::Add,mut terms) => {
+                if let Some(lead) = terms.iter().position(|it| it.negated().is_none()).and_then(|at| terms.remove(at)) {
+                    terms.push_front(lead);
+                }
+                Term::Chain(Commutative::Add,terms)
+            }
+            term => term,
+        }
+    }
+}
+
+pub enum Solution<N> {
+    /// Every unknown has exactly one value, redundant equations are still noted.
+    Unique(Vec<(String,Term<N>)>, Vec<String>),
+    /// The pivot unknowns expressed in the remaining free ones.
+    Infinite(Vec<(String,Term<N>)>, Vec<String>),
+    None(Vec<String>),
+}
+
+/// Simultaneous linear equations, one row per equation.
+pub struct LinearSystem<N> {
+    unknowns:Vec<String>,
+    rows:Vec<Linear<N>>,
+}
+
+impl<N:Numeric> LinearSystem<N> {
+    pub fn new(evaluators:&[Evaluator<N>]) -> Result<Self,EvalError> {
+        let mut rows = Vec::new();
+        for (index,evaluator) in evaluators.iter().enumerate() {
+            if !evaluator.equation {
+                return Err(EvalError::InEquation(index + 1, EvalError::NotAnEquation.into()));
+            }
+            rows.push(Linear::from_term(&evaluator.formula).map_err(|err| EvalError::InEquation(index + 1, err.into()))?);
+        }
+        let mut unknowns = rows.iter().flat_map(|row| row.coefs.keys().cloned()).collect::<Vec<String>>();
+        unknowns.sort();
+        unknowns.dedup();
+        Ok(Self { unknowns, rows })
+    }
+
+    /// Fraction-free (Bareiss) elimination, so every intermediate entry stays exact.
+    /// Each row is augmented with the identity, recording which equations were combined into it.
+    pub fn solve(&self) -> Result<Solution<N>,EvalError> {
+        let (n,m) = (self.unknowns.len(),self.rows.len());
+        let mut matrix = self.rows.iter().enumerate().map(|(index,row)| {
+            let mut line = self.unknowns.iter().map(|name| row.coefs.get(name).cloned().unwrap_or_default()).collect::<Vec<N>>();
+            line.push(-row.constant.clone());
+            line.extend((0..m).map(|it| if it == index { N::from(1) } else { N::from(0) }));
+            line
+        }).collect::<Vec<Vec<N>>>();
+
+        let mut pivots = Vec::new();
+        let mut previous = N::from(1);
+        for col in 0..n {
+            let rank = pivots.len();
+            let Some(found) = (rank..m).find(|it| !matrix[*it][col].is_zero()) else { continue };
+            matrix.swap(rank, found);
+            let pivot = matrix[rank][col].clone();
+            for row in rank + 1..m {
+                let factor = matrix[row][col].clone();
+                for j in 0..matrix[row].len() {
+                    matrix[row][j] = div(&sub(&mul(&pivot, &matrix[row][j])?, &mul(&factor, &matrix[rank][j])?)?, &previous)?;
+                }
+            }
+            previous = pivot;
+            pivots.push(col);
+        }
+
+        let rank = pivots.len();
+        let mut inconsistent = Vec::new();
+        let mut dependent = Vec::new();
+        for line in &matrix[rank..] {
+            let equations = line[n + 1..].iter().enumerate()
+                .filter(|(_,coef)| !coef.is_zero())
+                .map(|(index,_)| index + 1)
+                .collect::<Vec<usize>>();
+            let Some((last,others)) = equations.split_last() else { continue };
+            if !line[n].is_zero() && others.is_empty() {
+                inconsistent.push(format!("Equation {} is never true",last));
+            } else if !line[n].is_zero() {
+                inconsistent.push(format!("Equations {} are inconsistent",Self::join(&equations)));
+            } else if others.is_empty() {
+                dependent.push(format!("Equation {} is always true",last));
+            } else {
+                let plural = if others.len() > 1 { "equations" } else { "equation" };
+                dependent.push(format!("Equation {} is dependent on {} {}",last,plural,Self::join(others)));
+            }
+        }
+        if !inconsistent.is_empty() {
+            return Ok(Solution::None(inconsistent));
+        }
+
+        let mut values:BTreeMap<usize,Linear<N>> = (0..n)
+            .filter(|col| !pivots.contains(col))
+            .map(|col| (col,Linear::unknown(&self.unknowns[col])))
+            .collect();
+        for (row,col) in pivots.iter().enumerate().rev() {
+            let line = &matrix[row];
+            let mut value = Linear::constant(line[n].clone());
+            for (j,other) in values.iter() {
+                if *j > *col {
+                    value = value.add(&other.clone().scale(-line[*j].clone())?)?;
+                }
+            }
+            values.insert(*col, value.scale(div(&N::from(1), &line[*col])?)?);
+        }
+        let solution = pivots.iter().map(|col| (self.unknowns[*col].clone(),values[col].to_term())).collect();
+        Ok(if rank == n { Solution::Unique(solution, dependent) } else { Solution::Infinite(solution, dependent) })
+    }
+
+    fn join(equations:&[usize]) -> String {
+        equations.iter().map(|it| it.to_string()).collect::<Vec<String>>().join(", ")
+    }
+}
+
+/// The `x` of `A @ x = b` by elimination, a column of `x` for each column of `b`,
+/// or `None` while an entry is not a number. Each row is an equation in the unknowns `x1, x2, ...`,
+/// so a system with many or no solutions is reported the way simultaneous equations are.
+pub fn solve_matrix<N:Numeric>(a:&Rows<N>, b:&Rows<N>) -> Result<Option<Rows<N>>,EvalError> {
+    let (system,right) = (matrix::shape(a),matrix::shape(b));
+    if system.0 != right.0 {
+        return Err(MatrixError::Solve(system, right).into());
+    }
+    let (Some(a),Some(b)) = (matrix::numbers(a),matrix::numbers(b)) else { return Ok(None) };
+    let unknowns = (1..=system.1).map(|it| format!("x{}",it)).collect::<Vec<String>>();
+    let mut solution = vec![Vec::new(); system.1];
+    for col in 0..right.1 {
+        let rows = a.iter().zip(&b).map(|(coefs,constants)| Linear {
+            coefs: unknowns.iter().cloned().zip(coefs.iter().cloned()).filter(|(_,coef)| !coef.is_zero()).collect(),
+            constant: -constants[col].clone(),
+        }).collect();
+        match (LinearSystem { unknowns: unknowns.clone(), rows }).solve()? {
+            Solution::Unique(values,_) => solution.iter_mut().zip(values).for_each(|(row,(_,value))| row.push(value)),
+            Solution::Infinite(values,dependent) => return Err(EvalError::DependentSystem(dependent.into_iter()
+                .chain(values.iter().map(|(name,value)| format!("{} = {}",name,value)))
+                .collect())),
+            Solution::None(inconsistent) => return Err(EvalError::InconsistentSystem(inconsistent)),
+        }
+    }
+    Ok(Some(solution))
+}
+
+impl<N:Numeric> Solution<N> {
+    pub fn print(&self, console:&mut ConsoleManager) {
+        match self {
+            Solution::Unique(values, dependent) => {
+                console.println("Solution:");
+                dependent.iter().for_each(|line| console.println(line.as_str()));
+                values.iter().for_each(|(name,value)| console.println(format!("{} = {}",name,value)));
+            }
+            Solution::Infinite(values, dependent) => {
+                console.println("Infinite solutions:");
+                dependent.iter().for_each(|line| console.println(line.as_str()));
+                values.iter().for_each(|(name,value)| console.println(format!("{} = {}",name,value)));
+            }
+            Solution::None(inconsistent) => {
+                console.println("No solution:");
+                inconsistent.iter().for_each(|line| console.println(line.as_str()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::{EvalError, Evaluator};
+    use crate::term::Term;
+    use crate::testing::{evaluator, value, N};
+    use super::{LinearSystem, Solution};
+
+    fn solve(equations:&[&str]) -> Result<Solution<N>,EvalError> {
+        let evaluators = equations.iter().map(|it| evaluator(it)).collect::<Result<Vec<Evaluator<N>>,EvalError>>()?;
+        LinearSystem::new(&evaluators)?.solve()
+    }
+
+    fn eval(formula:&str) -> Result<String,EvalError> {
+        value::<N>(formula, &[])
+    }
+
+    /// Each value as `name = value`, followed by the notes on the equations.
+    fn lines(values:&[(String,Term<N>)], notes:&[String]) -> Vec<String> {
+        values.iter().map(|(name,value)| format!("{} = {}",name,value)).chain(notes.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn unique() {
+        let Solution::Unique(values,notes) = solve(&["x + y = 3", "x - y = 1"]).unwrap() else { panic!("expected a unique solution") };
+        assert_eq!(lines(&values, &notes), ["x = 2", "y = 1"]);
+    }
+
+    #[test]
+    fn dependent() {
+        let Solution::Infinite(values,notes) = solve(&["x + y = 3", "2x + 2y = 6"]).unwrap() else { panic!("expected infinite solutions") };
+        assert_eq!(lines(&values, &notes), ["x = 3 - y", "Equation 2 is dependent on equation 1"]);
+    }
+
+    #[test]
+    fn redundant() {
+        let Solution::Unique(values,notes) = solve(&["x + y = 3", "x - y = 1", "2x = 4"]).unwrap() else { panic!("expected a unique solution") };
+        assert_eq!(lines(&values, &notes), ["x = 2", "y = 1", "Equation 3 is dependent on equations 1, 2"]);
+    }
+
+    #[test]
+    fn inconsistent() {
+        let Solution::None(notes) = solve(&["x + y = 3", "x + y = 4"]).unwrap() else { panic!("expected no solution") };
+        assert_eq!(notes, ["Equations 1, 2 are inconsistent"]);
+    }
+
+    #[test]
+    fn not_linear() {
+        assert_eq!(solve(&["x*y = 3", "x = 1"]).err().unwrap().to_string(), "Equation 1: Not linear: x*y");
+    }
+
+    #[test]
+    fn matrix_systems() {
+        assert_eq!(eval("solve([1, 2; 3, 4], [5; 6])").unwrap(), "[-4; 4.5]");
+        assert_eq!(eval("solve([1, 2; 3, 4], [5, 1; 6, 0])").unwrap(), "[-4, -2; 4.5, 1.5]");
+        assert_eq!(eval("solve([1, 0; 0, 1; 1, 1], [1; 2; 3])").unwrap(), "[1; 2]");
+        assert_eq!(eval("solve([1, 2], [5; 6])").err().unwrap().to_string(), "Can not solve a 1x2 system for a 2x1 right side");
+    }
+
+    #[test]
+    fn singular_matrix_systems() {
+        assert_eq!(eval("solve([1, 1; 2, 2], [3; 6])").err().unwrap().to_string(),
+            "Infinite solutions: Equation 2 is dependent on equation 1; x1 = 3 - x2");
+        assert_eq!(eval("solve([1, 1; 2, 2], [3; 7])").err().unwrap().to_string(), "No solution: Equations 1, 2 are inconsistent");
+    }
+}
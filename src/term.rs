@@ -1,15 +1,16 @@
-use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, VecDeque};
 use std::fmt::{Debug, Display, Formatter};
-use rust_decimal::Decimal;
+use crate::constant::Constant;
+use crate::numeric::Numeric;
 
-pub type Num = Decimal;
-pub struct Equation(pub Option<Term>,pub Option<Term>);
+pub struct Equation<N>(pub Option<Term<N>>,pub Option<Term<N>>);
 
-impl Equation {
-    pub fn to_string(&self) -> String {
+impl<N:Numeric> Display for Equation<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let left = self.0.as_ref().map(|it| it.to_string()).unwrap_or("".to_string());
         let right = self.1.as_ref().map(|it| it.to_string()).unwrap_or("".to_string());
-        format!("{} = {}",left,right)
+        write!(f,"{} = {}",left,right)
     }
 }
 #[derive(PartialEq,Eq,Clone)]
@@ -18,71 +19,271 @@ pub enum Commutative{
     Multiply,
 }
 
-impl Commutative{
-    pub fn to_string(&self) -> String {
+impl Display for Commutative{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Add => "+".to_string(),
-            Self::Multiply => "*".to_string(),
+            Self::Add => write!(f,"+"),
+            Self::Multiply => write!(f,"*"),
         }
     }
 }
-#[derive(PartialEq,Eq,Clone)]
-pub enum Term {
-    Number(Num),
-    Chain(Commutative, VecDeque<Term>),
-    Power(Option<Box<Term>>,Box<Term>),
-    MulInverse(Box<Term>),
-    Opposite(Box<Term>),
-    Unknown(String)
+/// Built-in elementary functions of one argument.
+#[derive(PartialEq,Eq,PartialOrd,Ord,Clone,Copy,Debug)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Ln,
+    Exp,
+    Sqrt,
+    Abs,
+}
+
+impl Function {
+    pub fn from_name(name:&str) -> Option<Self> {
+        match name {
+            "sin" => Some(Self::Sin),
+            "cos" => Some(Self::Cos),
+            "tan" => Some(Self::Tan),
+            "ln" => Some(Self::Ln),
+            "exp" => Some(Self::Exp),
+            "sqrt" => Some(Self::Sqrt),
+            "abs" => Some(Self::Abs),
+            _ => None,
+        }
+    }
+
+    /// Numeric value at `n`, `None` outside the domain.
+    pub fn apply<N:Numeric>(&self, n:&N) -> Option<N> {
+        match self {
+            Self::Sin => n.sin(),
+            Self::Cos => n.cos(),
+            Self::Tan => n.tan(),
+            Self::Ln => n.ln(),
+            Self::Exp => n.exp(),
+            Self::Sqrt => n.sqrt(),
+            Self::Abs => n.checked_abs(),
+        }
+    }
+}
+
+impl Display for Function{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Sin => "sin",
+            Self::Cos => "cos",
+            Self::Tan => "tan",
+            Self::Ln => "ln",
+            Self::Exp => "exp",
+            Self::Sqrt => "sqrt",
+            Self::Abs => "abs",
+        };
+        write!(f,"{}",name)
+    }
+}
+
+/// Built-in operations taking matrices, which a number is not accepted for.
+#[derive(PartialEq,Eq,PartialOrd,Ord,Clone,Copy,Debug)]
+pub enum MatrixFunction {
+    Transpose,
+    Det,
+    Inverse,
+    /// `solve(A, b)`, the `x` of `A @ x = b`.
+    Solve,
+}
+
+impl MatrixFunction {
+    pub fn from_name(name:&str) -> Option<Self> {
+        match name {
+            "transpose" => Some(Self::Transpose),
+            "det" => Some(Self::Det),
+            "inv" => Some(Self::Inverse),
+            "solve" => Some(Self::Solve),
+            _ => None,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Transpose | Self::Det | Self::Inverse => 1,
+            Self::Solve => 2,
+        }
+    }
+}
+
+impl Display for MatrixFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Transpose => "transpose",
+            Self::Det => "det",
+            Self::Inverse => "inv",
+            Self::Solve => "solve",
+        };
+        write!(f,"{}",name)
+    }
+}
+
+/// Comparison of two terms, true as `1` and false as `0`.
+#[derive(PartialEq,Eq,PartialOrd,Ord,Clone,Copy)]
+pub enum Relation {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Relation {
+    /// Symbols in the order they are searched for, so `<=` is not read as `<`.
+    pub const SYMBOLS:[(&'static str,Relation);6] = [
+        ("<=", Self::LessEqual),
+        (">=", Self::GreaterEqual),
+        ("==", Self::Equal),
+        ("!=", Self::NotEqual),
+        ("<", Self::Less),
+        (">", Self::Greater),
+    ];
+
+    pub fn holds(&self, ordering:Ordering) -> bool {
+        match self {
+            Self::Less => ordering.is_lt(),
+            Self::LessEqual => ordering.is_le(),
+            Self::Greater => ordering.is_gt(),
+            Self::GreaterEqual => ordering.is_ge(),
+            Self::Equal => ordering.is_eq(),
+            Self::NotEqual => ordering.is_ne(),
+        }
+    }
 }
 
-impl Display for Term{
+impl Display for Relation {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f,"{}",self.to_string())
+        let (symbol,_) = Self::SYMBOLS.iter().find(|(_,it)| it == self).expect("every relation has a symbol");
+        write!(f,"{}",symbol)
     }
 }
-impl Debug for Term{
+
+/// Boolean connective over conditions, `Not` taking a single operand.
+#[derive(PartialEq,Eq,PartialOrd,Ord,Clone,Copy)]
+pub enum Logic {
+    And,
+    Or,
+    Not,
+}
+
+impl Display for Logic {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f,"{}",self.to_string())
+        match self {
+            Self::And => write!(f,"and"),
+            Self::Or => write!(f,"or"),
+            Self::Not => write!(f,"not"),
+        }
     }
 }
-impl Term {
-    pub fn to_string(&self) -> String {
+
+#[derive(PartialEq,Eq,Clone)]
+pub enum Term<N> {
+    Number(N),
+    Chain(Commutative, VecDeque<Term<N>>),
+    Power(Option<Box<Term<N>>>,Box<Term<N>>),
+    MulInverse(Box<Term<N>>),
+    Opposite(Box<Term<N>>),
+    Function(Function, Box<Term<N>>),
+    /// Matrix operation on as many arguments as its arity.
+    MatrixFunction(MatrixFunction, Vec<Term<N>>),
+    /// Application of a user-defined function, resolved by `Functions::inline`.
+    Call(String, Vec<Term<N>>),
+    Unknown(String),
+    /// Unit of measure of the quantity it multiplies, such as the `m` of `3 m`.
+    Unit(String),
+    /// Matrix literal `[1,2;3,4]`, its entries row by row.
+    Matrix(Vec<Vec<Term<N>>>),
+    /// Matrix product `A @ B`, its factors kept in order as it does not commute.
+    MatrixProduct(VecDeque<Term<N>>),
+    Compare(Relation, Box<Term<N>>, Box<Term<N>>),
+    Logic(Logic, Vec<Term<N>>),
+    /// Value of the first branch whose condition holds, else of the last term if there is one.
+    Piecewise(Vec<(Term<N>,Term<N>)>, Option<Box<Term<N>>>),
+}
+
+/// The prefix form, listing the operands of each chain: `+ [ x , -3 ]`.
+impl<N:Numeric> Debug for Term<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let list = |terms:&mut dyn Iterator<Item=&Term<N>>| terms.map(|it| format!("{:?}",it)).collect::<Vec<String>>();
+        match self {
+            Self::Number(n) => write!(f,"{}",n),
+            Self::Chain(com, terms) => write!(f,"{} [ {} ]",com,list(&mut terms.iter()).join(" , ")),
+            Self::MulInverse(term) => write!(f,"1/{:?}",term),
+            Self::Opposite(term) => write!(f,"-{:?}",term),
+            Self::Function(func,term) => write!(f,"{}({:?})",func,term),
+            Self::MatrixFunction(func,args) => write!(f,"{}({})",func,list(&mut args.iter()).join(", ")),
+            Self::Call(name,args) => write!(f,"{}({})",name,list(&mut args.iter()).join(", ")),
+            Self::Unknown(c) => write!(f,"{}",c),
+            Self::Unit(name) => write!(f,"{}",name),
+            Self::Matrix(rows) => write!(f,"[{}]",rows.iter().map(|row| list(&mut row.iter()).join(", ")).collect::<Vec<String>>().join("; ")),
+            Self::MatrixProduct(factors) => write!(f,"@ [ {} ]",list(&mut factors.iter()).join(" , ")),
+            Self::Compare(relation,left,right) => write!(f,"{} [ {:?} , {:?} ]",relation,left,right),
+            Self::Logic(logic,terms) => write!(f,"{} [ {} ]",logic,list(&mut terms.iter()).join(" , ")),
+            Self::Piecewise(branches,otherwise) => {
+                let mut args = list(&mut branches.iter().flat_map(|(cond,value)| [cond,value]));
+                args.extend(otherwise.iter().map(|it| format!("{:?}",it)));
+                write!(f,"piecewise({})",args.join(", "))
+            }
+            Self::Power(base,exp) => {
+                let base = base.as_ref().map(|it| format!("{:?}",it)).unwrap_or("none".to_string());
+                write!(f,"{}^{:?}",base,exp)
+            }
+        }
+    }
+}
+impl<N:Numeric> Term<N> {
+    /// Names of every unknown appearing in the term, sorted. Named constants are not unknowns.
+    pub fn unknowns(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.collect_unknowns(&mut names);
+        names
+    }
+
+    fn collect_unknowns(&self, names:&mut BTreeSet<String>) {
         match self {
-            Self::Number(n) => n.to_string(),
-            Self::Chain(com, terms) => format!("{} [ {} ]",com.to_string(),terms.iter().map(|it| it.to_string()).collect::<Vec<String>>().join(" , ")),
-            Self::MulInverse(term) => format!("1/{}",term.to_string()),
-            Self::Opposite(term) => format!("-{}",term.to_string()),
-            Self::Unknown(c) => c.to_string(),
+            Self::Unknown(name) => if Constant::find(name).is_none() { names.insert(name.clone()); },
+            Self::Chain(_, terms) | Self::MatrixProduct(terms) => terms.iter().for_each(|it| it.collect_unknowns(names)),
+            Self::Matrix(rows) => rows.iter().flatten().for_each(|it| it.collect_unknowns(names)),
+            Self::Compare(_,left,right) => {
+                left.collect_unknowns(names);
+                right.collect_unknowns(names);
+            }
+            Self::Logic(_,terms) => terms.iter().for_each(|it| it.collect_unknowns(names)),
+            Self::Piecewise(branches,otherwise) => {
+                branches.iter().flat_map(|(cond,value)| [cond,value]).for_each(|it| it.collect_unknowns(names));
+                if let Some(otherwise) = otherwise { otherwise.collect_unknowns(names) }
+            }
+            Self::MulInverse(term) | Self::Opposite(term) | Self::Function(_,term) => term.collect_unknowns(names),
+            Self::Call(_,args) | Self::MatrixFunction(_,args) => args.iter().for_each(|it| it.collect_unknowns(names)),
             Self::Power(base,exp) => {
-                let base = base.as_ref().map(|it| it.to_string()).unwrap_or("none".to_string());
-                let exp = exp.to_string();
-                format!("{}^{}",base,exp)
+                if let Some(base) = base { base.collect_unknowns(names) }
+                exp.collect_unknowns(names)
             }
+            Self::Number(_) | Self::Unit(_) => {}
         }
     }
 }
 
-#[derive(PartialEq,Eq)]
+#[derive(PartialEq,Eq,Clone,Copy)]
 pub enum Operator{
     Add,
     Subtract,
     Multiply,
     Divide,
     Power,
+    MatrixProduct,
     Equals,
 }
 
 impl Operator{
 
-    pub fn com(&self) -> bool {
-        match self {
-            Self::Add | Self::Multiply => true,
-            _ => false,
-        }
-    }
-
-    pub fn to_com(&self) -> Commutative {
+    pub fn to_com(self) -> Commutative {
         match self {
             Self::Add => Commutative::Add,
             Self::Subtract => Commutative::Add,
@@ -91,34 +292,13 @@ impl Operator{
             _ => panic!("Invalid Operator!"),
         }
     }
-    pub fn to_com_term(&self, term:Term) -> Term {
+    pub fn to_com_term<N>(self, term:Term<N>) -> Term<N> {
         match self {
             Self::Subtract => Term::Opposite(term.into()),
             Self::Divide => Term::MulInverse(term.into()),
             _ => term,
         }
     }
-
-    pub(crate) fn priority(&self) -> i32{
-        match self {
-            Self::Equals => 0,
-            Self::Add => 1,
-            Self::Subtract => 1,
-            Self::Multiply => 2,
-            Self::Divide => 2,
-            Self::Power => 3,
-        }
-    }
-    fn to_string(&self) -> String{
-        match self {
-            Self::Add => "+".to_string(),
-            Self::Subtract => "-".to_string(),
-            Self::Multiply => "*".to_string(),
-            Self::Divide => "/".to_string(),
-            Self::Power => "^".to_string(),
-            Self::Equals => "=".to_string(),
-        }
-    }
 }
 impl From<char> for Operator{
     fn from(c:char) -> Self{
@@ -128,11 +308,25 @@ impl From<char> for Operator{
             '*' => Self::Multiply,
             '/' => Self::Divide,
             '^' => Self::Power,
+            '@' => Self::MatrixProduct,
             '=' => Self::Equals,
             _ => panic!("Invalid Operator {c}!"),
         }
     }
 }
+impl Display for Operator{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Add => write!(f,"+"),
+            Self::Subtract => write!(f,"-"),
+            Self::Multiply => write!(f,"*"),
+            Self::Divide => write!(f,"/"),
+            Self::Power => write!(f,"^"),
+            Self::MatrixProduct => write!(f,"@"),
+            Self::Equals => write!(f,"="),
+        }
+    }
+}
 impl Operator{
     pub(crate) fn from_char(c:char) -> Option<Self>{
         match c {
@@ -141,6 +335,7 @@ impl Operator{
             '*' => Some(Self::Multiply),
             '/' => Some(Self::Divide),
             '^' => Some(Self::Power),
+            '@' => Some(Self::MatrixProduct),
             '=' => Some(Self::Equals),
             _ => None,
         }
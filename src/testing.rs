@@ -0,0 +1,36 @@
+//! Shorthands shared by the unit tests: the number type the REPL starts with, and input known to be valid.
+
+use std::collections::HashMap;
+use crate::complex::Complex;
+use crate::eval::{EvalError, Evaluator};
+use crate::num::Num;
+use crate::numeric::Numeric;
+use crate::parser::parse;
+use crate::term::{Equation, Term};
+
+/// Exact rationals with an imaginary part, the default number type.
+pub type N = Complex<Num>;
+
+/// Parses `s`, failing the test when it is not valid.
+pub fn equation<M:Numeric>(s:&str) -> Equation<M> {
+    parse(s).unwrap_or_else(|err| panic!("Can not parse {}: {}",s,err))
+}
+
+/// Parses `s`, a formula without `=`.
+pub fn formula<M:Numeric>(s:&str) -> Term<M> {
+    match equation(s) {
+        Equation(None,Some(term)) => term,
+        _ => panic!("{} is not a formula",s),
+    }
+}
+
+/// Compiles the formula or equation `s`.
+pub fn evaluator<M:Numeric>(s:&str) -> Result<Evaluator<M>,EvalError> {
+    Evaluator::new(equation(s))
+}
+
+/// The value of the formula `s`, its unknowns bound to the integers in `bindings`, printed.
+pub fn value<M:Numeric>(s:&str, bindings:&[(&str,i32)]) -> Result<String,EvalError> {
+    let context = bindings.iter().map(|(name,value)| (name.to_string(),Term::Number(M::from(*value)))).collect::<HashMap<String,Term<M>>>();
+    Ok(evaluator::<M>(s)?.eval(&context)?.to_string())
+}
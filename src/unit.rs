@@ -0,0 +1,337 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::numeric::Numeric;
+use crate::parser;
+use crate::term::{Commutative, Function, MatrixFunction, Term};
+
+/// Base units of the SI, one per base dimension, in the order of `Dimension`'s exponents.
+const BASE:[&str;7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// A unit of measure, written in brackets after a number as in `3 [m]` or `9.81 [m/s^2]`.
+pub struct Unit {
+    pub name:&'static str,
+    definition:Definition,
+    pub description:&'static str,
+}
+
+enum Definition {
+    /// Index of the base dimension the unit measures.
+    Base(usize),
+    /// A quantity in other units, such as `1000 m` for `km`.
+    Derived(&'static str),
+}
+
+pub const UNITS:&[Unit] = &[
+    Unit { name: "m", definition: Definition::Base(0), description: "metre" },
+    Unit { name: "kg", definition: Definition::Base(1), description: "kilogram" },
+    Unit { name: "s", definition: Definition::Base(2), description: "second" },
+    Unit { name: "A", definition: Definition::Base(3), description: "ampere" },
+    Unit { name: "K", definition: Definition::Base(4), description: "kelvin, differences only as the scale is not offset" },
+    Unit { name: "mol", definition: Definition::Base(5), description: "mole" },
+    Unit { name: "cd", definition: Definition::Base(6), description: "candela" },
+    Unit { name: "km", definition: Definition::Derived("1000 m"), description: "kilometre" },
+    Unit { name: "cm", definition: Definition::Derived("0.01 m"), description: "centimetre" },
+    Unit { name: "mm", definition: Definition::Derived("0.001 m"), description: "millimetre" },
+    Unit { name: "g", definition: Definition::Derived("0.001 kg"), description: "gram" },
+    Unit { name: "t", definition: Definition::Derived("1000 kg"), description: "tonne" },
+    Unit { name: "ms", definition: Definition::Derived("0.001 s"), description: "millisecond" },
+    Unit { name: "min", definition: Definition::Derived("60 s"), description: "minute" },
+    Unit { name: "h", definition: Definition::Derived("60 min"), description: "hour" },
+    Unit { name: "day", definition: Definition::Derived("24 h"), description: "day" },
+    Unit { name: "L", definition: Definition::Derived("0.001 m^3"), description: "litre" },
+    Unit { name: "Hz", definition: Definition::Derived("1 s^-1"), description: "hertz" },
+    Unit { name: "N", definition: Definition::Derived("1 kg*m/s^2"), description: "newton" },
+    Unit { name: "Pa", definition: Definition::Derived("1 N/m^2"), description: "pascal" },
+    Unit { name: "J", definition: Definition::Derived("1 N*m"), description: "joule" },
+    Unit { name: "W", definition: Definition::Derived("1 J/s"), description: "watt" },
+    Unit { name: "C", definition: Definition::Derived("1 A*s"), description: "coulomb" },
+    Unit { name: "V", definition: Definition::Derived("1 W/A"), description: "volt" },
+    Unit { name: "ohm", definition: Definition::Derived("1 V/A"), description: "ohm" },
+    Unit { name: "in", definition: Definition::Derived("0.0254 m"), description: "inch" },
+    Unit { name: "ft", definition: Definition::Derived("12 in"), description: "foot" },
+    Unit { name: "yd", definition: Definition::Derived("3 ft"), description: "yard" },
+    Unit { name: "mi", definition: Definition::Derived("5280 ft"), description: "mile" },
+    Unit { name: "mph", definition: Definition::Derived("1 mi/h"), description: "mile per hour" },
+    Unit { name: "lb", definition: Definition::Derived("0.45359237 kg"), description: "pound" },
+    Unit { name: "oz", definition: Definition::Derived("0.0625 lb"), description: "ounce" },
+    Unit { name: "lbf", definition: Definition::Derived("4.4482216152605 N"), description: "pound-force" },
+    Unit { name: "psi", definition: Definition::Derived("1 lbf/in^2"), description: "pound-force per square inch" },
+    Unit { name: "gal", definition: Definition::Derived("3.785411784 L"), description: "US gallon" },
+];
+
+/// Why a quantity could not be measured or converted.
+#[derive(Debug)]
+pub enum UnitError {
+    UnknownUnit(String),
+    /// A unit whose definition does not read, or is out of range for the number type.
+    InvalidDefinition(&'static str),
+    /// Quantities of different dimensions added, compared or chosen between, the action named.
+    Mismatch(&'static str,Dimension,Dimension),
+    DimensionedExponent(String),
+    /// A quantity raised to an exponent that is not a number.
+    SymbolicExponent(Dimension),
+    /// A power or root that would leave a base dimension with a fractional exponent.
+    FractionalDimension(String),
+    /// A function such as `sin` applied to a quantity with a dimension.
+    DimensionedArgument(Function,Dimension),
+    NotQuantity(String),
+    Incompatible(Dimension,Dimension),
+}
+
+impl Display for UnitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownUnit(name) => write!(f, "Unknown unit {}", name),
+            Self::InvalidDefinition(name) => write!(f, "Invalid definition of unit {}", name),
+            Self::Mismatch(action,left,right) => write!(f, "Can not {} {} and {}", action, left, right),
+            Self::DimensionedExponent(exp) => write!(f, "Exponent {} must be a number", exp),
+            Self::SymbolicExponent(base) => write!(f, "Exponent of a quantity in {} must be a number", base),
+            Self::FractionalDimension(what) => write!(f, "{} has a fractional dimension", what),
+            Self::DimensionedArgument(func,dimension) => write!(f, "{} of a quantity in {} is undefined", func, dimension),
+            Self::NotQuantity(value) => write!(f, "Can not convert {}", value),
+            Self::Incompatible(from,to) => write!(f, "Can not convert {} to {}", from, to),
+        }
+    }
+}
+
+impl Error for UnitError {}
+
+impl Unit {
+    pub fn find(name:&str) -> Option<&'static Unit> {
+        UNITS.iter().find(|it| it.name == name)
+    }
+
+    /// The unit as a quantity in base units, `km` as `1000*m`.
+    fn in_base<N:Numeric>(&self) -> Result<Term<N>,UnitError> {
+        match self.definition {
+            Definition::Base(_) => Ok(Term::Unit(self.name.to_string())),
+            Definition::Derived(definition) => {
+                let invalid = || UnitError::InvalidDefinition(self.name);
+                let (value,units) = definition.split_once(' ').ok_or_else(invalid)?;
+                let value = N::parse(value).ok_or_else(invalid)?;
+                let units = parser::parse_units::<N>(units).map_err(|_| invalid())?;
+                Ok(Term::product(vec![Term::Number(value), units.to_base_units()?.simplify()]))
+            }
+        }
+    }
+
+    fn dimension<N:Numeric>(&self) -> Result<Dimension,UnitError> {
+        match self.definition {
+            Definition::Base(index) => {
+                let mut exps = [0;7];
+                exps[index] = 1;
+                Ok(Dimension(exps))
+            }
+            Definition::Derived(definition) => {
+                let invalid = || UnitError::InvalidDefinition(self.name);
+                let (_,units) = definition.split_once(' ').ok_or_else(invalid)?;
+                parser::parse_units::<N>(units).map_err(|_| invalid())?.dimension()?.ok_or_else(invalid)
+            }
+        }
+    }
+}
+
+/// Exponents of the SI base dimensions, `m/s^2` being `[1,0,-2,0,0,0,0]`.
+#[derive(PartialEq,Eq,Clone,Copy,Default,Debug)]
+pub struct Dimension([i32;7]);
+
+impl Dimension {
+    fn is_dimensionless(&self) -> bool {
+        self.0.iter().all(|it| *it == 0)
+    }
+
+    fn mul(self, other:Dimension) -> Self {
+        Self(std::array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+
+    fn inverse(self) -> Self {
+        Self(self.0.map(|exp| -exp))
+    }
+
+    fn pow<N:Numeric>(self, exp:&N) -> Option<Self> {
+        let mut exps = [0;7];
+        for (i,it) in self.0.iter().enumerate() {
+            exps[i] = (N::from(*it) * exp).to_i128()?.try_into().ok()?;
+        }
+        Some(Self(exps))
+    }
+}
+
+impl Display for Dimension {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_dimensionless() {
+            return write!(f,"1");
+        }
+        let units = BASE.iter().zip(self.0.iter())
+            .filter(|(_,exp)| **exp != 0)
+            .map(|(unit,exp)| if *exp == 1 { unit.to_string() } else { format!("{}^{}",unit,exp) })
+            .collect::<Vec<String>>();
+        write!(f,"{}",units.join("*"))
+    }
+}
+
+impl<N:Numeric> Term<N> {
+    /// Dimension of the quantity the term stands for, failing where incompatible dimensions are added.
+    /// Unknowns, constants and calls are not checked, so `None` means any dimension fits.
+    pub fn dimension(&self) -> Result<Option<Dimension>,UnitError> {
+        let dimension = match self {
+            Term::Number(_) => Some(Dimension::default()),
+            Term::Unit(name) => Some(Unit::find(name).ok_or_else(|| UnitError::UnknownUnit(name.clone()))?.dimension::<N>()?),
+            Term::Unknown(_) | Term::Call(..) | Term::Power(None,_) => None,
+            Term::Opposite(term) => term.dimension()?,
+            Term::MulInverse(term) => term.dimension()?.map(Dimension::inverse),
+            Term::Chain(Commutative::Add,terms) => Self::common_dimension("add", terms.iter())?,
+            Term::Matrix(rows) => Self::common_dimension("add", rows.iter().flatten())?,
+            Term::Compare(_,left,right) => {
+                Self::common_dimension("compare", [left.as_ref(),right.as_ref()].into_iter())?;
+                Some(Dimension::default())
+            }
+            Term::Logic(_,terms) => {
+                for term in terms {
+                    term.dimension()?;
+                }
+                Some(Dimension::default())
+            }
+            Term::Piecewise(branches,otherwise) => {
+                for (cond,_) in branches {
+                    cond.dimension()?;
+                }
+                Self::common_dimension("choose between", branches.iter().map(|(_,value)| value).chain(otherwise.as_deref()))?
+            }
+            Term::Chain(Commutative::Multiply,terms) | Term::MatrixProduct(terms) => {
+                let mut product = Some(Dimension::default());
+                for term in terms {
+                    product = product.zip(term.dimension()?).map(|(left,right)| left.mul(right));
+                }
+                product
+            }
+            Term::Power(Some(base),exp) => {
+                if exp.dimension()?.is_some_and(|it| !it.is_dimensionless()) {
+                    return Err(UnitError::DimensionedExponent(exp.to_string()));
+                }
+                match (base.dimension()?,exp.as_ref()) {
+                    (Some(base),_) if base.is_dimensionless() => Some(base),
+                    (Some(base),Term::Number(exp)) => Some(base.pow(exp).ok_or_else(|| UnitError::FractionalDimension(format!("({})^{}",base,exp)))?),
+                    (Some(base),_) => return Err(UnitError::SymbolicExponent(base)),
+                    (None,_) => None,
+                }
+            }
+            Term::Function(func,arg) => match (func,arg.dimension()?) {
+                (_,None) => None,
+                (Function::Abs,dimension) => dimension,
+                (Function::Sqrt,Some(dimension)) => Some(dimension.pow(&(N::from(1) / N::from(2))).ok_or_else(|| UnitError::FractionalDimension(format!("sqrt of a quantity in {}",dimension)))?),
+                (_,Some(dimension)) if dimension.is_dimensionless() => Some(dimension),
+                (_,Some(dimension)) => return Err(UnitError::DimensionedArgument(*func, dimension)),
+            },
+            // The entries of a determinant or a solution multiply each other, so only the shape-preserving operations keep a dimension.
+            Term::MatrixFunction(func,args) => match (func,args.as_slice()) {
+                (MatrixFunction::Transpose,[arg]) => arg.dimension()?,
+                (MatrixFunction::Inverse,[arg]) => arg.dimension()?.map(Dimension::inverse),
+                _ => None,
+            },
+        };
+        Ok(dimension)
+    }
+
+    /// Dimension shared by terms that are added up, compared or collected in a matrix, `action` naming the failure.
+    fn common_dimension<'a>(action:&'static str, terms:impl Iterator<Item=&'a Term<N>>) -> Result<Option<Dimension>,UnitError> where N:'a {
+        let mut common:Option<Dimension> = None;
+        for term in terms {
+            match (common,term.dimension()?) {
+                (Some(left),Some(right)) if left != right => return Err(UnitError::Mismatch(action, left, right)),
+                (None,right) => common = right,
+                _ => {}
+            }
+        }
+        Ok(common)
+    }
+
+    /// Number of a quantity in base units, such as `3` for `3*m*s^-1`.
+    pub(crate) fn magnitude(&self) -> Option<N> {
+        let is_unit = |term:&Term<N>| match term {
+            Term::Unit(_) => true,
+            Term::Power(Some(base),exp) => matches!((base.as_ref(),exp.as_ref()),(Term::Unit(_),Term::Number(_))),
+            _ => false,
+        };
+        match self {
+            Term::Number(n) => Some(n.clone()),
+            Term::Chain(Commutative::Multiply,factors) => match factors.front() {
+                Some(Term::Number(n)) if factors.iter().skip(1).all(is_unit) => Some(n.clone()),
+                _ => None,
+            },
+            _ if is_unit(self) => Some(N::from(1)),
+            _ => None,
+        }
+    }
+
+    /// Replaces every unit with its value in base units, so `1 km + 1 m` can be added up as `1001*m`.
+    pub fn to_base_units(&self) -> Result<Term<N>,UnitError> {
+        let convert = |term:&Term<N>| term.to_base_units().map(Box::new);
+        let converted = match self {
+            Term::Unit(name) => Unit::find(name).ok_or_else(|| UnitError::UnknownUnit(name.clone()))?.in_base()?,
+            Term::Number(_) | Term::Unknown(_) => self.clone(),
+            Term::Chain(com,terms) => Term::Chain(com.clone(), terms.iter().map(Term::to_base_units).collect::<Result<_,_>>()?),
+            Term::Power(base,exp) => Term::Power(base.as_deref().map(convert).transpose()?, convert(exp)?),
+            Term::MulInverse(term) => Term::MulInverse(convert(term)?),
+            Term::Opposite(term) => Term::Opposite(convert(term)?),
+            Term::Function(func,term) => Term::Function(*func, convert(term)?),
+            Term::Call(name,args) => Term::Call(name.clone(), args.iter().map(Term::to_base_units).collect::<Result<_,_>>()?),
+            Term::MatrixFunction(func,args) => Term::MatrixFunction(*func, args.iter().map(Term::to_base_units).collect::<Result<_,_>>()?),
+            Term::Matrix(rows) => Term::Matrix(rows.iter().map(|row| row.iter().map(Term::to_base_units).collect()).collect::<Result<_,_>>()?),
+            Term::MatrixProduct(factors) => Term::MatrixProduct(factors.iter().map(Term::to_base_units).collect::<Result<_,_>>()?),
+            Term::Compare(relation,left,right) => Term::Compare(*relation, convert(left)?, convert(right)?),
+            Term::Logic(logic,terms) => Term::Logic(*logic, terms.iter().map(Term::to_base_units).collect::<Result<_,_>>()?),
+            Term::Piecewise(branches,otherwise) => Term::Piecewise(
+                branches.iter().map(|(cond,value)| Ok((cond.to_base_units()?, value.to_base_units()?))).collect::<Result<_,UnitError>>()?,
+                otherwise.as_deref().map(convert).transpose()?,
+            ),
+        };
+        Ok(converted)
+    }
+}
+
+/// Value of the quantity `value` measured in `units`, such as `25/3` for `30000*m*h^-1` in `km/h`.
+pub fn convert<N:Numeric>(value:&Term<N>, units:&Term<N>) -> Result<N,UnitError> {
+    let (from,to) = (value.dimension()?,units.dimension()?);
+    let Some(from) = from else { return Err(UnitError::NotQuantity(value.to_string())) };
+    if Some(from) != to {
+        return Err(UnitError::Incompatible(from, to.unwrap_or_default()));
+    }
+    let ratio = Term::product(vec![value.to_base_units()?, Term::power(units.to_base_units()?.simplify(), Term::Number(N::from(-1)))]);
+    match ratio.simplify() {
+        Term::Number(n) => Ok(n),
+        _ => Err(UnitError::NotQuantity(value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_units;
+    use crate::testing::{formula, value, N};
+    use super::convert;
+
+    fn eval(s:&str) -> String {
+        value::<N>(s, &[]).unwrap_or_else(|err| err.to_string())
+    }
+
+    #[test]
+    fn same_dimension() {
+        assert_eq!(eval("1 [km] + 1 [m]"), "1001*[m]");
+        assert_eq!(eval("2 [m] * 3 [m]"), "6*[m]^2");
+        assert_eq!(eval("1 [h] > 59 [min]"), "1");
+    }
+
+    #[test]
+    fn mismatch() {
+        assert_eq!(eval("2 [m] + 3 [s]"), "Can not add m and s");
+        assert_eq!(eval("sin(2 [m])"), "sin of a quantity in m is undefined");
+    }
+
+    #[test]
+    fn conversion() {
+        let speed = formula::<N>("10 [m/s]");
+        assert_eq!(convert(&speed, &parse_units("km/h").unwrap()).unwrap().to_string(), "36");
+        assert_eq!(convert(&speed, &parse_units("[km/h]").unwrap()).unwrap().to_string(), "36");
+        assert_eq!(convert(&speed, &parse_units("kg").unwrap()).err().unwrap().to_string(), "Can not convert m*s^-1 to kg");
+    }
+}